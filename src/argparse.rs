@@ -26,6 +26,13 @@ pub enum SubCommand {
     Worktree(WorktreePicker),
     /// Git Jump
     GitJump(GitJump),
+    /// Manage local branches
+    #[clap(subcommand)]
+    Branch(BranchPicker),
+    /// Report unsaved work and unfinished git operations across every project and worktree
+    Status(Status),
+    /// Run a command in every project and linked worktree
+    Foreach(Foreach),
 }
 
 #[derive(Parser, Debug)]
@@ -40,6 +47,39 @@ pub enum WorktreePicker {
     Cleanup(WorktreeCleanup),
     /// Remove worktrees across all repos under the worktree root
     CleanupAll(WorktreeCleanupAll),
+    /// List worktrees across all repos under the worktree root
+    List(WorktreeList),
+    /// Pick another worktree of the current repo and print its path
+    Switch(WorktreeSwitch),
+    /// Protect a linked worktree from pruning and removal
+    Lock(WorktreeLock),
+    /// Remove the lock from a linked worktree
+    Unlock(WorktreeUnlock),
+    /// Rename a linked worktree within its directory under the worktree root
+    Move(WorktreeMove),
+    /// Move worktrees from old slug directory layouts under the worktree root
+    Migrate(WorktreeMigrate),
+    /// Show disk usage of worktrees per repo under the worktree root
+    Du(WorktreeDu),
+    /// Find and fix orphaned slug dirs, stale admin entries and broken worktree links
+    Doctor(WorktreeDoctor),
+    /// Change the directories checked out in a sparse worktree
+    #[clap(subcommand)]
+    Sparse(WorktreeSparse),
+}
+
+#[derive(Parser, Debug)]
+pub enum WorktreeSparse {
+    /// Check out more directories
+    Add(WorktreeSparseDirs),
+    /// Stop checking out directories
+    Remove(WorktreeSparseDirs),
+}
+
+#[derive(Parser, Debug)]
+pub enum BranchPicker {
+    /// Delete local branches that are merged or whose upstream is gone
+    Prune(BranchPrune),
 }
 
 #[derive(Parser, Debug)]
@@ -70,10 +110,16 @@ pub enum TmuxRename {
     Force,
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 pub struct GitJump {
     /// Root directories to scan from
     pub root: Option<PathBuf>,
+    /// Gather branches from every repository in the configured projects
+    #[clap(long, conflicts_with_all = &["root", "delete-merged"])]
+    pub all_projects: bool,
+    /// Override config path, used with `--all-projects`
+    #[clap(long)]
+    pub config: Option<PathBuf>,
     /// Only show commits by the configured author
     #[clap(long)]
     pub use_author: bool,
@@ -86,15 +132,89 @@ pub struct GitJump {
     /// Instead of showing the commit log, preview details about the commit
     #[clap(long)]
     pub preview_commit_details: bool,
+    /// Include annotated and lightweight tags as targets
+    #[clap(long)]
+    pub tags: bool,
+    /// Include stash entries as targets, selecting one applies it
+    #[clap(long)]
+    pub stashes: bool,
+    /// Include the most recent HEAD reflog positions as targets
+    #[clap(long, value_name = "COUNT")]
+    pub reflog: Option<usize>,
+    /// Pick merged local branches with multi-select and delete them
+    #[clap(long)]
+    pub delete_merged: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct BranchPrune {
+    /// Repository to prune, defaults to the current directory
+    pub root: Option<PathBuf>,
+    /// List the branches that would be deleted without deleting them
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Only prune branches whose tip commit is older than this (e.g. `30d`, `12h`, `2w`)
+    #[clap(long, value_parser = parse_age)]
+    pub older_than: Option<chrono::Duration>,
+    /// Only prune branches whose tip commit is by the configured author
+    #[clap(long)]
+    pub use_author: bool,
+    /// Regex of branch names to never delete, added to `branches.protect`
+    #[clap(long)]
+    pub protect: Vec<String>,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
 pub struct Test {}
 
+#[derive(Parser, Debug)]
+pub struct Foreach {
+    /// Only run in projects of the group with this title
+    #[clap(long)]
+    pub group: Option<String>,
+    /// Only run in checkouts in this state
+    #[clap(long, value_enum)]
+    pub filter: Option<ForeachFilter>,
+    /// Commands run at once [default: available cores]
+    #[clap(short, long)]
+    pub jobs: Option<usize>,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+    /// Command and its arguments, after `--`
+    #[clap(required = true, last = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Parser, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ForeachFilter {
+    /// Uncommitted or untracked changes
+    Dirty,
+    /// Branch behind its upstream
+    Behind,
+}
+
+#[derive(Parser, Debug)]
+pub struct Status {
+    /// Also list checkouts that need no attention
+    #[clap(long)]
+    pub all: bool,
+    /// Print JSON instead of a table
+    #[clap(long)]
+    pub json: bool,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
 #[derive(Parser, Debug)]
 pub struct WorktreeCreate {
-    /// Worktree directory name
-    pub name: String,
+    /// Worktree directory name, defaults to the fetched branch with --from-remote or --fetch-ref;
+    /// without one a branch is picked with skim
+    pub name: Option<String>,
     /// Create or checkout this branch name in the new worktree
     #[clap(short = 'b', long, conflicts_with = "detach")]
     pub branch: Option<String>,
@@ -102,7 +222,24 @@ pub struct WorktreeCreate {
     #[clap(short = 'd', long, conflicts_with = "branch")]
     pub detach: bool,
     /// Optional commit-ish (branch, tag, or commit)
+    #[clap(requires = "name")]
     pub commitish: Option<String>,
+    /// Fetch `<remote>/<branch>` and create a local branch tracking it
+    #[clap(
+        long,
+        value_name = "REMOTE/BRANCH",
+        conflicts_with_all = &["detach", "commitish", "fetch-ref"]
+    )]
+    pub from_remote: Option<String>,
+    /// Fetch any ref, e.g. `refs/pull/123/head`, and create a branch at it
+    #[clap(long, value_name = "REF", conflicts_with = "commitish")]
+    pub fetch_ref: Option<String>,
+    /// Remote to fetch --fetch-ref from [default: origin]
+    #[clap(long, requires = "fetch-ref")]
+    pub remote: Option<String>,
+    /// Check out only the directories of this `worktrees.sparse` profile
+    #[clap(long, value_name = "PROFILE")]
+    pub sparse: Option<String>,
     /// Override config path
     #[clap(long)]
     pub config: Option<PathBuf>,
@@ -110,14 +247,198 @@ pub struct WorktreeCreate {
 
 #[derive(Parser, Debug)]
 pub struct WorktreeCleanup {
+    /// Remove worktrees with uncommitted, unpushed or stashed work without asking
+    #[clap(long)]
+    pub force: bool,
+    /// Also delete the branch of each removed worktree when merged or its upstream is gone
+    #[clap(long)]
+    pub delete_branch: bool,
+    /// Like --delete-branch, and delete merged branches on their upstream remote too
+    #[clap(long)]
+    pub delete_remote: bool,
     /// Override config path
     #[clap(long)]
     pub config: Option<PathBuf>,
 }
 
+#[derive(Parser, Debug)]
+pub struct WorktreeSwitch {
+    /// Rename tmux window behavior
+    #[clap(long, value_enum)]
+    pub tmux_rename: Option<TmuxRename>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeLock {
+    /// Name of the linked worktree, defaults to the current one
+    #[clap(long)]
+    pub worktree: Option<String>,
+    /// Why the worktree is locked, shown by `git worktree list`
+    #[clap(long)]
+    pub reason: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeUnlock {
+    /// Name of the linked worktree, defaults to the current one
+    #[clap(long)]
+    pub worktree: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeMove {
+    /// New worktree directory name
+    pub new_name: String,
+    /// Name of the linked worktree, defaults to the current one
+    #[clap(long)]
+    pub worktree: Option<String>,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeSparseDirs {
+    /// Directories relative to the repository root
+    #[clap(required = true)]
+    pub dirs: Vec<String>,
+    /// Name of the linked worktree, defaults to the current one
+    #[clap(long)]
+    pub worktree: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeMigrate {
+    /// Print the moves without making them
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeDu {
+    /// Show one row per worktree instead of per repo
+    #[clap(long)]
+    pub worktrees: bool,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeDoctor {
+    /// Print the problems and their fixes without applying them
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Apply every fix without asking
+    #[clap(short, long)]
+    pub yes: bool,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct WorktreeList {
+    /// Print JSON instead of a table
+    #[clap(long)]
+    pub json: bool,
+    /// Order of the listed worktrees
+    #[clap(long, value_enum, default_value = "repo")]
+    pub sort: WorktreeSort,
+    /// Override config path
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug, Clone, Copy, clap::ValueEnum)]
+pub enum WorktreeSort {
+    /// By repository, then worktree name
+    Repo,
+    /// Oldest last commit first
+    Age,
+    /// Largest first
+    Size,
+}
+
 #[derive(Parser, Debug)]
 pub struct WorktreeCleanupAll {
+    /// Only remove worktrees whose branch is merged into `origin/HEAD`
+    #[clap(long)]
+    pub merged: bool,
+    /// Only remove worktrees whose last commit is older than this (e.g. `30d`, `12h`, `2w`)
+    #[clap(long, value_parser = parse_age)]
+    pub older_than: Option<chrono::Duration>,
+    /// Only remove worktrees whose directory is missing
+    #[clap(long)]
+    pub prunable: bool,
+    /// Only remove worktrees without uncommitted, unpushed or stashed work
+    #[clap(long)]
+    pub clean_only: bool,
+    /// Only remove worktrees of repositories whose path matches this regex
+    #[clap(long, value_name = "PATTERN")]
+    pub repo: Option<String>,
+    /// Print the worktrees matching the filters without removing them
+    #[clap(long)]
+    pub dry_run: bool,
+    /// Remove the matching worktrees without asking; ones with unsaved work still need --force
+    #[clap(long, short = 'y')]
+    pub yes: bool,
+    /// Remove worktrees with uncommitted, unpushed or stashed work without asking
+    #[clap(long)]
+    pub force: bool,
+    /// Also delete the branch of each removed worktree when merged or its upstream is gone
+    #[clap(long)]
+    pub delete_branch: bool,
+    /// Like --delete-branch, and delete merged branches on their upstream remote too
+    #[clap(long)]
+    pub delete_remote: bool,
     /// Override config path
     #[clap(long)]
     pub config: Option<PathBuf>,
 }
+
+/// Parse ages like `30d`, `12h`, `2w` or `90m`; a bare number is a count of days.
+pub fn parse_age(text: &str) -> Result<chrono::Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number = number
+        .parse::<i64>()
+        .map_err(|_| format!("invalid age `{}`, expected e.g. `30d`", text))?;
+    match unit {
+        "" | "d" => Ok(chrono::Duration::days(number)),
+        "w" => Ok(chrono::Duration::weeks(number)),
+        "h" => Ok(chrono::Duration::hours(number)),
+        "m" => Ok(chrono::Duration::minutes(number)),
+        "s" => Ok(chrono::Duration::seconds(number)),
+        _ => Err(format!(
+            "unknown age unit `{}`, expected one of s, m, h, d, w",
+            unit
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_age;
+
+    #[test]
+    fn parse_age_supports_units() {
+        assert_eq!(parse_age("30d"), Ok(chrono::Duration::days(30)));
+        assert_eq!(parse_age("2w"), Ok(chrono::Duration::weeks(2)));
+        assert_eq!(parse_age("12h"), Ok(chrono::Duration::hours(12)));
+        assert_eq!(parse_age("7"), Ok(chrono::Duration::days(7)));
+    }
+
+    #[test]
+    fn parse_age_rejects_garbage() {
+        assert!(parse_age("").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3y").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+
+use crate::{
+    argparse,
+    cmd::gitjump::{self, GitTarget},
+    config, git,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PruneReason {
+    Merged,
+    UpstreamGone,
+}
+
+impl std::fmt::Display for PruneReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PruneReason::Merged => write!(f, "merged"),
+            PruneReason::UpstreamGone => write!(f, "gone"),
+        }
+    }
+}
+
+/// Why a local branch can go: merged into `primary`, or its upstream was deleted.
+pub(crate) fn prune_reason(
+    repo: &git2::Repository,
+    primary: Option<git2::Oid>,
+    name: &str,
+) -> anyhow::Result<Option<PruneReason>> {
+    let branch = repo
+        .find_branch(name, git2::BranchType::Local)
+        .with_context(|| format!("could not find local branch `{}`", name))?;
+    let tip = branch
+        .get()
+        .peel_to_commit()
+        .with_context(|| format!("could not resolve local branch `{}`", name))?
+        .id();
+    if let Some(primary) = primary {
+        if repo.merge_base(primary, tip).ok() == Some(tip) {
+            return Ok(Some(PruneReason::Merged));
+        }
+    }
+    if git::is_upstream_gone(repo, name) {
+        return Ok(Some(PruneReason::UpstreamGone));
+    }
+    Ok(None)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PruneCandidate {
+    name: String,
+    reason: PruneReason,
+}
+
+impl PruneCandidate {
+    /// Output line for the branch; gone branches are not merged, so losing commits is spelled out.
+    fn describe(&self, dry_run: bool) -> String {
+        match (self.reason, dry_run) {
+            (PruneReason::Merged, _) => format!("{}\t{}", self.name, self.reason),
+            (PruneReason::UpstreamGone, true) => format!(
+                "{}\t{}\twould force-delete unmerged commits",
+                self.name, self.reason
+            ),
+            (PruneReason::UpstreamGone, false) => format!(
+                "{}\t{}\tforce-deleted unmerged commits",
+                self.name, self.reason
+            ),
+        }
+    }
+}
+
+struct PruneFilter {
+    protect: regex::RegexSet,
+    protected_names: HashSet<String>,
+    older_than: Option<chrono::Duration>,
+    author: Option<String>,
+    now: i64,
+}
+
+impl PruneFilter {
+    fn allows(&self, target: &GitTarget, branch_name: &str) -> bool {
+        if self.protected_names.contains(branch_name) || self.protect.is_match(branch_name) {
+            log::debug!("`{}` is protected", branch_name);
+            return false;
+        }
+        if let Some(older_than) = self.older_than {
+            let age = self.now - target.commit.time.seconds();
+            if age < older_than.num_seconds() {
+                log::trace!("`{}` is newer than the age threshold", branch_name);
+                return false;
+            }
+        }
+        if let Some(author) = &self.author {
+            if &target.commit.author != author {
+                log::trace!(
+                    "skipping `{}` authored by {}",
+                    branch_name,
+                    target.commit.author
+                );
+                return false;
+            }
+        }
+        true
+    }
+}
+
+pub fn prune(args: &argparse::BranchPrune) -> anyhow::Result<()> {
+    let start_dir = args
+        .root
+        .clone()
+        .map(Ok)
+        .unwrap_or_else(std::env::current_dir)?;
+    let repo = git2::Repository::discover(start_dir).context("git")?;
+
+    let mut protect = args.protect.clone();
+    match config::load_config(args.config.as_deref()) {
+        Ok(cfg) => protect.extend(cfg.branches.protect),
+        Err(err) => log::debug!("not using branch protection from config: {:#}", err),
+    }
+    let protect = regex::RegexSet::new(&protect).context("invalid branch protection pattern")?;
+
+    let mut protected_names = git::checked_out_branches(&repo);
+    protected_names.extend(gitjump::primary_branch_name(&repo));
+
+    let author = if args.use_author {
+        Some(gitjump::configured_author(&repo)?.context("user.name is not valid utf-8")?)
+    } else {
+        None
+    };
+
+    let filter = PruneFilter {
+        protect,
+        protected_names,
+        older_than: args.older_than,
+        author,
+        now: chrono::Utc::now().timestamp(),
+    };
+
+    let candidates = find_prune_candidates(&repo, &filter)?;
+    if candidates.is_empty() {
+        log::warn!("no branches to prune");
+        return Ok(());
+    }
+
+    let mut failures = Vec::new();
+    for candidate in &candidates {
+        if !args.dry_run {
+            if let Err(err) = git::delete_local_branch(&repo, &candidate.name) {
+                failures.push(format!("{}: {:#}", candidate.name, err));
+                continue;
+            }
+        }
+        println!("{}", candidate.describe(args.dry_run));
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("failed to delete some branches:\n{}", failures.join("\n"));
+    }
+
+    Ok(())
+}
+
+fn find_prune_candidates(
+    repo: &git2::Repository,
+    filter: &PruneFilter,
+) -> anyhow::Result<Vec<PruneCandidate>> {
+    let primary = gitjump::primary_commit(repo);
+    if primary.is_none() {
+        log::warn!("`origin/HEAD` is not set, only pruning branches whose upstream is gone");
+    }
+
+    let mut candidates = Vec::new();
+    for target in gitjump::build_targets(repo, true, gitjump::TargetSources::default())? {
+        for branch in &target.branches {
+            if branch.branch_type != git2::BranchType::Local {
+                continue;
+            }
+            let Some(reason) = prune_reason(repo, primary, &branch.name)? else {
+                continue;
+            };
+            if !filter.allows(&target, &branch.name) {
+                continue;
+            }
+            candidates.push(PruneCandidate {
+                name: branch.name.clone(),
+                reason,
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_util;
+
+    fn filter() -> PruneFilter {
+        PruneFilter {
+            protect: regex::RegexSet::empty(),
+            protected_names: HashSet::new(),
+            older_than: None,
+            author: None,
+            now: chrono::Utc::now().timestamp(),
+        }
+    }
+
+    fn setup_repo(name: &str) -> (std::path::PathBuf, git2::Repository) {
+        let path = test_util::unique_temp_path(name);
+        let repo = test_util::init_repo(&path);
+        {
+            let base = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.branch("merged", &base, false).unwrap();
+            repo.branch("release/1.0", &base, false).unwrap();
+
+            let unmerged = test_util::commit_file(&repo, "feature.txt", "wip\n", "wip");
+            let unmerged = repo.find_commit(unmerged).unwrap();
+            repo.branch("unmerged", &unmerged, false).unwrap();
+            repo.branch("gone", &unmerged, false).unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("branch.gone.remote", "origin").unwrap();
+            config
+                .set_str("branch.gone.merge", "refs/heads/gone")
+                .unwrap();
+            repo.remote("origin", "https://example.com/repo.git")
+                .unwrap();
+
+            repo.reference("refs/remotes/origin/main", base.id(), false, "test")
+                .unwrap();
+            repo.reference_symbolic(
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/main",
+                false,
+                "test",
+            )
+            .unwrap();
+        }
+        (path, repo)
+    }
+
+    fn names(candidates: &[PruneCandidate]) -> Vec<(&str, PruneReason)> {
+        candidates
+            .iter()
+            .map(|c| (c.name.as_str(), c.reason))
+            .collect()
+    }
+
+    #[test]
+    fn finds_merged_and_gone_branches() {
+        let (path, repo) = setup_repo("prune-basic");
+        let mut filter = filter();
+        filter.protected_names = git::checked_out_branches(&repo);
+
+        let got = find_prune_candidates(&repo, &filter).expect("candidates");
+
+        assert_eq!(
+            names(&got),
+            vec![
+                ("gone", PruneReason::UpstreamGone),
+                ("merged", PruneReason::Merged),
+                ("release/1.0", PruneReason::Merged),
+            ]
+        );
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn protection_patterns_and_age_are_respected() {
+        let (path, repo) = setup_repo("prune-protect");
+        let mut filter = filter();
+        filter.protected_names = git::checked_out_branches(&repo);
+        filter.protect = regex::RegexSet::new(["^release/"]).unwrap();
+
+        let got = find_prune_candidates(&repo, &filter).expect("candidates");
+        assert_eq!(
+            names(&got),
+            vec![
+                ("gone", PruneReason::UpstreamGone),
+                ("merged", PruneReason::Merged),
+            ]
+        );
+
+        filter.older_than = Some(chrono::Duration::days(1));
+        let got = find_prune_candidates(&repo, &filter).expect("candidates");
+        assert!(got.is_empty());
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn prune_reason_for_single_branch() {
+        let (path, repo) = setup_repo("prune-reason");
+        let primary = gitjump::primary_commit(&repo);
+
+        let reason = |name| prune_reason(&repo, primary, name).expect("reason");
+        assert_eq!(reason("merged"), Some(PruneReason::Merged));
+        assert_eq!(reason("gone"), Some(PruneReason::UpstreamGone));
+        assert_eq!(reason("unmerged"), None);
+        assert!(prune_reason(&repo, primary, "missing").is_err());
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn gone_branches_are_reported_as_force_deleted() {
+        let candidate = |reason| PruneCandidate {
+            name: "feature".to_string(),
+            reason,
+        };
+
+        assert_eq!(
+            candidate(PruneReason::Merged).describe(true),
+            "feature\tmerged"
+        );
+        assert_eq!(
+            candidate(PruneReason::UpstreamGone).describe(true),
+            "feature\tgone\twould force-delete unmerged commits"
+        );
+        assert_eq!(
+            candidate(PruneReason::UpstreamGone).describe(false),
+            "feature\tgone\tforce-deleted unmerged commits"
+        );
+    }
+
+    #[test]
+    fn author_filter_skips_other_authors() {
+        let (path, repo) = setup_repo("prune-author");
+        let mut filter = filter();
+        filter.protected_names = git::checked_out_branches(&repo);
+        filter.author = Some("Bob".to_string());
+
+        let got = find_prune_candidates(&repo, &filter).expect("candidates");
+        assert!(got.is_empty());
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+}
@@ -0,0 +1,234 @@
+use std::{
+    io::{BufRead, BufReader, IsTerminal, Read},
+    num::NonZeroUsize,
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+};
+
+use crate::{
+    argparse::{self, ForeachFilter},
+    cmd::project::{scan_projects, Project},
+    config::load_config,
+    git,
+    parallel::parallel_map,
+    skim_style,
+};
+
+/// What happened in one project.
+#[derive(Debug)]
+enum Outcome {
+    /// Left out by `--filter`
+    Skipped,
+    Finished(ExitStatus),
+    NotStarted(String),
+}
+
+pub fn foreach(args: &argparse::Foreach) -> anyhow::Result<()> {
+    let mut config = load_config(args.config.as_deref())?;
+    let directories = match &args.group {
+        Some(group) => {
+            config
+                .projects
+                .retain(|project_group| &project_group.title == group);
+            if config.projects.is_empty() {
+                anyhow::bail!("no project group titled `{}`", group);
+            }
+            // manual directories do not belong to a group
+            Vec::new()
+        }
+        None => config.directories,
+    };
+    let mut projects = Vec::new();
+    scan_projects(config.projects, directories, &mut |project| {
+        projects.push(project);
+        Ok(())
+    });
+
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(NonZeroUsize::get)
+                .unwrap_or(1)
+        })
+        .max(1);
+    let color = std::io::stdout().is_terminal();
+    let outcomes = run_all(&projects, &args.command, args.filter, jobs, color);
+
+    let mut ran = 0;
+    let mut failures = Vec::new();
+    for (project, outcome) in projects.iter().zip(&outcomes) {
+        let failure = match outcome {
+            Outcome::Skipped => continue,
+            Outcome::Finished(status) if status.success() => None,
+            Outcome::Finished(status) => Some(match status.code() {
+                Some(code) => format!("exit code {}", code),
+                None => "killed by a signal".to_string(),
+            }),
+            Outcome::NotStarted(err) => Some(format!("failed to start: {}", err)),
+        };
+        ran += 1;
+        if let Some(failure) = failure {
+            failures.push(format!(
+                "  {} ({}): {}",
+                project.label(),
+                project.path.display(),
+                failure
+            ));
+        }
+    }
+
+    let skipped = outcomes.len() - ran;
+    if skipped > 0 {
+        eprintln!("skipped {} projects not matching --filter", skipped);
+    }
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "`{}` failed in {} of {} projects:\n{}",
+            args.command.join(" "),
+            failures.len(),
+            ran,
+            failures.join("\n")
+        );
+    }
+    eprintln!("`{}` succeeded in {} projects", args.command.join(" "), ran);
+    Ok(())
+}
+
+/// Run `command` in each project with at most `jobs` at once; outcomes are in project order.
+fn run_all(
+    projects: &[Project],
+    command: &[String],
+    filter: Option<ForeachFilter>,
+    jobs: usize,
+    color: bool,
+) -> Vec<Outcome> {
+    parallel_map(projects, jobs, |project| {
+        if filter.is_some_and(|filter| !matches_filter(&project.path, filter)) {
+            return Outcome::Skipped;
+        }
+        run_in(project, command, color)
+    })
+}
+
+fn matches_filter(path: &Path, filter: ForeachFilter) -> bool {
+    match filter {
+        ForeachFilter::Dirty => git::inspect_unsaved_work(path)
+            .map(|work| work.is_dirty())
+            .unwrap_or(false),
+        ForeachFilter::Behind => is_behind_upstream(path).unwrap_or(false),
+    }
+}
+
+fn is_behind_upstream(path: &Path) -> Option<bool> {
+    let repo = git2::Repository::open(path).ok()?;
+    let head = repo.head().ok().filter(|head| head.is_branch())?;
+    let branch = git2::Branch::wrap(head);
+    match git::upstream_state(&repo, &branch)? {
+        git::UpstreamState::Tracking { behind, .. } => Some(behind > 0),
+        git::UpstreamState::Gone => Some(false),
+    }
+}
+
+/// Run the command inside the project, prefixing each line of its output with the project label.
+fn run_in(project: &Project, command: &[String], color: bool) -> Outcome {
+    let label = format!("[{}]", project.label());
+    let prefix = if color {
+        skim_style::project_tag_style(project.project_color)
+            .paint(label)
+            .to_string()
+    } else {
+        label
+    };
+
+    let mut child = match Command::new(&command[0])
+        .args(&command[1..])
+        .current_dir(&project.path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => return Outcome::NotStarted(err.to_string()),
+    };
+
+    let stdout = child.stdout.take().expect("piped stdout");
+    let stderr = child.stderr.take().expect("piped stderr");
+    std::thread::scope(|scope| {
+        scope.spawn(|| forward_lines(stderr, |line| eprintln!("{} {}", prefix, line)));
+        forward_lines(stdout, |line| println!("{} {}", prefix, line));
+    });
+
+    match child.wait() {
+        Ok(status) => Outcome::Finished(status),
+        Err(err) => Outcome::NotStarted(err.to_string()),
+    }
+}
+
+/// Hand each line to `emit` as it arrives; output that is not UTF-8 is shown lossily.
+fn forward_lines(reader: impl Read, emit: impl Fn(&str)) {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let text = String::from_utf8_lossy(&line);
+                emit(text.trim_end_matches(['\n', '\r']));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_util;
+
+    fn project(path: std::path::PathBuf) -> Project {
+        Project {
+            title: path.file_name().unwrap().to_string_lossy().to_string(),
+            path,
+            typename: "work".to_string(),
+            worktree: None,
+            project_color: None,
+        }
+    }
+
+    #[test]
+    fn runs_in_matching_projects_and_keeps_their_exit_codes() {
+        let root = test_util::unique_temp_path("foreach");
+        test_util::init_repo(&root.join("clean"));
+        test_util::init_repo(&root.join("dirty"));
+        std::fs::write(root.join("dirty/notes.txt"), "todo\n").unwrap();
+        let projects = vec![project(root.join("clean")), project(root.join("dirty"))];
+        let command = ["sh", "-c", "test -f notes.txt && exit 3"].map(ToString::to_string);
+
+        let all = run_all(&projects, &command, None, 2, false);
+        let dirty = run_all(&projects, &command, Some(ForeachFilter::Dirty), 1, false);
+        let missing = run_all(
+            &projects,
+            &["shelf-no-such-command".to_string()],
+            None,
+            2,
+            false,
+        );
+
+        let codes = |outcomes: &[Outcome]| {
+            outcomes
+                .iter()
+                .map(|outcome| match outcome {
+                    Outcome::Skipped => "skipped".to_string(),
+                    Outcome::Finished(status) => format!("exit {:?}", status.code()),
+                    Outcome::NotStarted(_) => "not started".to_string(),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(codes(&all), vec!["exit Some(1)", "exit Some(3)"]);
+        assert_eq!(codes(&dirty), vec!["skipped", "exit Some(3)"]);
+        assert_eq!(codes(&missing), vec!["not started", "not started"]);
+        std::fs::remove_dir_all(&root).expect("cleanup repos");
+    }
+}
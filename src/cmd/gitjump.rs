@@ -1,19 +1,30 @@
 use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
+    io::Write,
+    process::{Command, Stdio},
     sync::Arc,
 };
 
 use anyhow::Context;
-use skim::{prelude::SkimOptionsBuilder, Skim, SkimItem, SkimItemReceiver, SkimItemSender};
+use skim::{
+    prelude::{Event, SkimOptionsBuilder},
+    Skim, SkimItem, SkimItemReceiver, SkimItemSender,
+};
 use terminal_size::terminal_size;
 
 use crate::{
     argparse,
-    git::{GitBranch, GitCommit, GitRef},
+    cmd::project::{scan_projects, Project},
+    config::load_config,
+    git::{self, GitBranch, GitCommit, GitRef, GitReflogEntry, GitStash, GitTag, UpstreamState},
+    prompt, skim_style, worktree,
 };
 
 const BRANCH_ICON: &str = "";
+const TAG_ICON: &str = "";
+const STASH_ICON: &str = "";
+const REFLOG_ICON: &str = "";
 const WINDOW_SPLIT_MIN_SIZE: u16 = 160;
 const RELATIVE_TIME_LOOKBACK_DAYS: i64 = 6;
 const RELATIVE_TIME_LOOKBACK_HOURS: i64 = 4;
@@ -21,21 +32,33 @@ const RELATIVE_TIME_LOOKBACK_HOURS: i64 = 4;
 #[derive(Debug, Clone)]
 struct SkimGitTarget {
     inner: GitTarget,
+    project: Option<Arc<Project>>,
     preview_details: bool,
     display_str: skim::AnsiString<'static>,
 }
 
 impl SkimGitTarget {
-    fn new(target: GitTarget, preview_details: bool) -> SkimGitTarget {
-        let ansi_str = format!(
-            "{}",
-            DisplayLine {
-                target: &target,
-                collapse_pushed: true,
-            }
-        );
+    fn new(
+        target: GitTarget,
+        preview_details: bool,
+        project: Option<Arc<Project>>,
+    ) -> SkimGitTarget {
+        let line = DisplayLine {
+            target: &target,
+            collapse_pushed: true,
+        };
+        let ansi_str = match &project {
+            Some(project) => format!(
+                "{} {}",
+                skim_style::project_tag_style(project.project_color)
+                    .paint(format!("[{}]", project.title)),
+                line
+            ),
+            None => format!("{}", line),
+        };
         SkimGitTarget {
             inner: target,
+            project,
             preview_details,
             display_str: skim::AnsiString::parse(&ansi_str),
         }
@@ -43,12 +66,48 @@ impl SkimGitTarget {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct GitTarget {
-    repo_path: std::path::PathBuf,
-    commit: GitCommit,
-    branches: Vec<GitBranch>,
-    is_merged: bool,
-    is_primary: bool,
+pub(crate) struct GitTarget {
+    pub(crate) repo_path: std::path::PathBuf,
+    pub(crate) commit: GitCommit,
+    pub(crate) branches: Vec<GitBranch>,
+    pub(crate) tags: Vec<GitTag>,
+    pub(crate) stash: Option<GitStash>,
+    pub(crate) reflog: Option<GitReflogEntry>,
+    pub(crate) is_merged: bool,
+    pub(crate) is_primary: bool,
+}
+
+impl GitTarget {
+    fn new(repo: &git2::Repository, commit: GitCommit) -> GitTarget {
+        GitTarget {
+            repo_path: repo.path().to_owned(),
+            commit,
+            branches: Vec::with_capacity(1),
+            tags: Vec::new(),
+            stash: None,
+            reflog: None,
+            is_merged: false,
+            is_primary: false,
+        }
+    }
+}
+
+/// Ref sources collected in addition to local and remote branches.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TargetSources {
+    pub(crate) tags: bool,
+    pub(crate) stashes: bool,
+    pub(crate) reflog: usize,
+}
+
+impl TargetSources {
+    fn from_args(args: &argparse::GitJump) -> TargetSources {
+        TargetSources {
+            tags: args.tags,
+            stashes: args.stashes,
+            reflog: args.reflog.unwrap_or(0),
+        }
+    }
 }
 
 struct DisplayLine<'a> {
@@ -69,6 +128,15 @@ impl<'a> DisplayLine<'a> {
             GREY
         }
     }
+    fn tag_color(&self) -> ansi_term::Color {
+        ansi_term::Color::Green
+    }
+    fn stash_color(&self) -> ansi_term::Color {
+        ansi_term::Color::Purple
+    }
+    fn reflog_color(&self) -> ansi_term::Color {
+        ansi_term::Color::Cyan
+    }
 }
 
 fn is_remote_of(local: &str, inspect: &str) -> bool {
@@ -102,9 +170,11 @@ impl<'a> std::fmt::Display for DisplayLine<'a> {
                 branch_style.paint("("),
             )?;
             'b: for (idx, branch) in target.branches.iter().enumerate() {
-                for s in &seen {
-                    if is_remote_of(s, branch.ref_name.as_str()) {
-                        continue 'b;
+                if self.collapse_pushed {
+                    for s in &seen {
+                        if is_remote_of(s, branch.ref_name.as_str()) {
+                            continue 'b;
+                        }
                     }
                 }
                 seen.insert(branch.ref_name.as_str());
@@ -115,10 +185,47 @@ impl<'a> std::fmt::Display for DisplayLine<'a> {
                     write!(f, "{}", branch_style.bold().paint("*"))?;
                 }
                 write!(f, "{}", branch_style.paint(&branch.name))?;
+                write!(f, "{}", DisplayUpstream(branch.upstream))?;
             }
             write!(f, "{}", branch_style.paint(")"))?;
         }
 
+        if !target.tags.is_empty() {
+            let tag_style = self.tag_color();
+            let names = target
+                .tags
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            write!(
+                f,
+                " {}{}",
+                tag_style.paint(TAG_ICON),
+                tag_style.paint(format!("({})", names))
+            )?;
+        }
+
+        if let Some(stash) = &target.stash {
+            let stash_style = self.stash_color();
+            write!(
+                f,
+                " {}{}",
+                stash_style.paint(STASH_ICON),
+                stash_style.paint(format!("(stash@{{{}}})", stash.index))
+            )?;
+        }
+
+        if let Some(reflog) = &target.reflog {
+            let reflog_style = self.reflog_color();
+            write!(
+                f,
+                " {}{}",
+                reflog_style.paint(REFLOG_ICON),
+                reflog_style.paint(format!("(HEAD@{{{}}})", reflog.index))
+            )?;
+        }
+
         write!(f, " {}", target.commit.message.trim())?;
 
         write!(f, " {}", ansi_term::ANSIStrings(author))?;
@@ -127,16 +234,41 @@ impl<'a> std::fmt::Display for DisplayLine<'a> {
     }
 }
 
+struct DisplayUpstream(Option<UpstreamState>);
+
+impl std::fmt::Display for DisplayUpstream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(UpstreamState::Tracking { ahead, behind }) => {
+                if ahead > 0 {
+                    write!(
+                        f,
+                        " {}",
+                        skim_style::ahead_style().paint(format!("↑{}", ahead))
+                    )?;
+                }
+                if behind > 0 {
+                    write!(
+                        f,
+                        " {}",
+                        skim_style::behind_style().paint(format!("↓{}", behind))
+                    )?;
+                }
+                Ok(())
+            }
+            Some(UpstreamState::Gone) => {
+                write!(f, " {}", skim_style::upstream_gone_style().paint("gone"))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
 struct DisplayTime(i64);
 
 impl std::fmt::Display for DisplayTime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let dt =
-            chrono::NaiveDateTime::from_timestamp_opt(self.0, 0).expect("invalid unix timestamp");
-        let ut = dt
-            .and_local_timezone(chrono::prelude::Utc)
-            .latest()
-            .expect("unix timestamp did not make sense in UTC");
+        let ut = chrono::DateTime::from_timestamp(self.0, 0).expect("invalid unix timestamp");
         let lt = ut.with_timezone(&chrono::prelude::Local);
 
         let now = chrono::prelude::Local::now();
@@ -157,7 +289,7 @@ impl std::fmt::Display for DisplayTime {
 
 impl PartialOrd for GitTarget {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.commit.cmp(&other.commit))
+        Some(self.cmp(other))
     }
 }
 
@@ -169,7 +301,10 @@ impl Ord for GitTarget {
 
 impl SkimItem for SkimGitTarget {
     fn text(&self) -> std::borrow::Cow<'_, str> {
-        Cow::Owned(format!("{:?}", self.inner))
+        match &self.project {
+            Some(project) => Cow::Owned(format!("[{}] {:?}", project.title, self.inner)),
+            None => Cow::Owned(format!("{:?}", self.inner)),
+        }
     }
     fn preview(&self, _context: skim::PreviewContext) -> skim::ItemPreview {
         let target = &self.inner;
@@ -190,18 +325,29 @@ impl SkimItem for SkimGitTarget {
     }
 }
 
-fn build_targets(
-    args: &argparse::GitJump,
+pub(crate) fn build_targets(
     repo: &git2::Repository,
+    show_all_branches: bool,
+    sources: TargetSources,
 ) -> anyhow::Result<Vec<GitTarget>> {
     let mut target_map = HashMap::new();
 
     build_branches(repo, &mut target_map).context("failed to extract branches")?;
+    if sources.tags {
+        build_tags(repo, &mut target_map).context("failed to extract tags")?;
+    }
+    if sources.stashes {
+        build_stashes(repo, &mut target_map).context("failed to extract stashes")?;
+    }
+    if sources.reflog > 0 {
+        build_reflog(repo, sources.reflog, &mut target_map).context("failed to extract reflog")?;
+    }
 
     let mut results = target_map.into_values().collect::<Vec<_>>();
     results.iter_mut().for_each(|t| {
         t.branches.sort();
-        if !args.show_all_branches {
+        t.tags.sort();
+        if !show_all_branches {
             t.branches.truncate(1)
         }
     });
@@ -227,6 +373,10 @@ fn build_branches(
             }
         };
         let head = branch.is_head();
+        let upstream = match branch_type {
+            git2::BranchType::Local => git::upstream_state(repo, &branch),
+            git2::BranchType::Remote => None,
+        };
         let c = match GitCommit::from_branch(&branch) {
             Ok(c) => c,
             Err(e) => {
@@ -239,76 +389,630 @@ fn build_branches(
             ref_name: GitRef::from(branch).to_string(),
             branch_type,
             head,
+            upstream,
         };
-        let entry = map.entry(c.id).or_insert(GitTarget {
-            repo_path: repo.path().to_owned(),
-            commit: c,
-            branches: Vec::with_capacity(1),
-            is_merged: false,
-            is_primary: false,
-        });
+        let entry = map.entry(c.id).or_insert_with(|| GitTarget::new(repo, c));
         entry.branches.push(branch);
     }
     Ok(())
 }
 
+fn build_tags(
+    repo: &git2::Repository,
+    map: &mut HashMap<git2::Oid, GitTarget>,
+) -> anyhow::Result<()> {
+    for name in repo.tag_names(None)?.iter().flatten() {
+        let reference = match repo.find_reference(&format!("refs/tags/{}", name)) {
+            Ok(r) => r,
+            Err(e) => {
+                log::error!("could not read tag `{}`: {}", name, e);
+                continue;
+            }
+        };
+        let commit = match reference.peel_to_commit() {
+            Ok(c) => c,
+            Err(e) => {
+                log::debug!("tag `{}` does not point at a commit: {}", name, e);
+                continue;
+            }
+        };
+        let annotated = reference
+            .target()
+            .map(|id| repo.find_tag(id).is_ok())
+            .unwrap_or(false);
+        let entry = map
+            .entry(commit.id())
+            .or_insert_with(|| GitTarget::new(repo, GitCommit::from_commit(&commit)));
+        entry.tags.push(GitTag {
+            name: name.to_owned(),
+            annotated,
+        });
+    }
+    Ok(())
+}
+
+fn build_stashes(
+    repo: &git2::Repository,
+    map: &mut HashMap<git2::Oid, GitTarget>,
+) -> anyhow::Result<()> {
+    if repo.find_reference("refs/stash").is_err() {
+        return Ok(());
+    }
+    let reflog = repo.reflog("refs/stash")?;
+    for (index, entry) in reflog.iter().enumerate() {
+        let commit = match repo.find_commit(entry.id_new()) {
+            Ok(c) => c,
+            Err(e) => {
+                log::error!("could not get commit for stash@{{{}}}: {}", index, e);
+                continue;
+            }
+        };
+        let target = map
+            .entry(commit.id())
+            .or_insert_with(|| GitTarget::new(repo, GitCommit::from_commit(&commit)));
+        target.stash = Some(GitStash {
+            index,
+            message: entry.message().unwrap_or("").to_owned(),
+        });
+    }
+    Ok(())
+}
+
+fn build_reflog(
+    repo: &git2::Repository,
+    limit: usize,
+    map: &mut HashMap<git2::Oid, GitTarget>,
+) -> anyhow::Result<()> {
+    let reflog = repo.reflog("HEAD")?;
+    for (index, entry) in reflog.iter().enumerate().take(limit) {
+        let commit = match repo.find_commit(entry.id_new()) {
+            Ok(c) => c,
+            Err(e) => {
+                log::debug!("could not get commit for HEAD@{{{}}}: {}", index, e);
+                continue;
+            }
+        };
+        let target = map
+            .entry(commit.id())
+            .or_insert_with(|| GitTarget::new(repo, GitCommit::from_commit(&commit)));
+        // Entries are newest first, so keep the most recent visit to each commit
+        if target.reflog.is_none() {
+            target.reflog = Some(GitReflogEntry {
+                index,
+                message: entry.message().unwrap_or("").to_owned(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Pick a local or remote branch with skim; a commit with several branches yields the local one.
+pub(crate) fn select_branch(
+    repo: &git2::Repository,
+    header: &str,
+) -> anyhow::Result<Option<GitBranch>> {
+    let targets = build_targets(repo, true, TargetSources::default())?;
+    let (send, recv): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
+    for target in targets {
+        send.send(Arc::new(SkimGitTarget::new(target, false, None)))
+            .context("unable to send branch to skim")?;
+    }
+    drop(send);
+
+    let options = SkimOptionsBuilder::default()
+        .header(Some(header))
+        .build()
+        .unwrap();
+    let Some(result) = Skim::run_with(&options, Some(recv)) else {
+        return Ok(None);
+    };
+    if result.is_abort {
+        return Ok(None);
+    }
+    Ok(result
+        .selected_items
+        .iter()
+        .filter_map(|item| item.as_any().downcast_ref::<SkimGitTarget>())
+        .find_map(|selected| selected.inner.branches.first().cloned()))
+}
+
+/// Commit the remote's default branch (`origin/HEAD`) points at, if known.
+pub(crate) fn primary_commit(repo: &git2::Repository) -> Option<git2::Oid> {
+    repo.refname_to_id("refs/remotes/origin/HEAD").ok()
+}
+
+/// Short name of the remote's default branch, e.g. `main` for `origin/HEAD -> origin/main`.
+pub(crate) fn primary_branch_name(repo: &git2::Repository) -> Option<String> {
+    let head = repo.find_reference("refs/remotes/origin/HEAD").ok()?;
+    let target = head.symbolic_target()?;
+    target
+        .strip_prefix("refs/remotes/origin/")
+        .map(ToOwned::to_owned)
+}
+
+fn mark_merge_state(repo: &git2::Repository, primary: git2::Oid, target: &mut GitTarget) {
+    // This is SLOW
+    if let Ok(x) = repo.merge_base(primary, target.commit.id) {
+        target.is_merged = x == target.commit.id;
+        target.is_primary = primary == target.commit.id;
+    }
+}
+
+/// Value of `user.name` for the repository, used by `--use-author` filters.
+pub(crate) fn configured_author(repo: &git2::Repository) -> anyhow::Result<Option<String>> {
+    let config = repo.config().context("get config")?;
+    let user = config.get_entry("user.name").context("get user.name")?;
+    Ok(user.value().map(ToOwned::to_owned))
+}
+
 pub fn jump(args: &argparse::GitJump) -> anyhow::Result<()> {
     log::trace!("{:?}", args);
 
+    if args.all_projects {
+        return jump_all_projects(args);
+    }
+
     let start_dir = args
         .root
         .clone()
         .map(Ok)
         .unwrap_or_else(std::env::current_dir)?;
 
-    let repo = git2::Repository::discover(start_dir).context("git")?;
+    let mut repo = git2::Repository::discover(start_dir).context("git")?;
     log::trace!("using {:?} as project dir", repo.path());
 
-    let config = repo.config().context("get config")?;
-    let user = config.get_entry("user.name").context("get user.name")?;
-    let name = user.value();
+    let recv = {
+        let (send, recv): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
+        send_repo_targets(args, &repo, None, &send)?;
+        recv
+    };
+    let (action, selected) = match select_targets(args, recv) {
+        Some(s) => s,
+        None => {
+            log::warn!("no selection was made");
+            return Ok(());
+        }
+    };
+    log::debug!("{:?} {:#?}", action, selected);
 
-    let targets = build_targets(args, &repo)?;
+    if args.delete_merged {
+        return delete_merged_targets(&repo, &selected);
+    }
+    let target = match selected.first() {
+        Some(t) => t,
+        None => {
+            log::warn!("no selection was made");
+            return Ok(());
+        }
+    };
 
-    let primary = repo.refname_to_id("refs/remotes/origin/HEAD").ok();
+    run_action(&mut repo, action, target)
+}
 
-    let recv = {
-        let (send, recv): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
-        for mut t in targets {
-            if args.use_author && Some(t.commit.author.as_str()) != name {
-                log::trace!("skipping commit authored by {}", t.commit.author);
-                continue;
-            }
+/// Pick a target across every repository found from the `ShelfConfig` project groups.
+///
+/// Checking out prints the directory the branch ended up in so a shell wrapper can `cd` there.
+fn jump_all_projects(args: &argparse::GitJump) -> anyhow::Result<()> {
+    let config = load_config(args.config.as_deref())?;
 
-            if let Some(primary) = primary {
-                // This is SLOW
-                if let Ok(x) = repo.merge_base(primary, t.commit.id) {
-                    t.is_merged = x == t.commit.id;
-                    t.is_primary = primary == t.commit.id;
+    let (send, recv): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
+    let thread_args = args.clone();
+    std::thread::spawn(move || {
+        scan_projects(config.projects, config.directories, &mut |project| {
+            if project.worktree.is_some() {
+                // branches are shared with the main checkout, which is scanned as well
+                return Ok(());
+            }
+            let repo = match git2::Repository::open(&project.path) {
+                Ok(repo) => repo,
+                Err(err) => {
+                    log::debug!("skipping `{}`: {}", project.path.display(), err);
+                    return Ok(());
                 }
+            };
+            if let Err(err) =
+                send_repo_targets(&thread_args, &repo, Some(&Arc::new(project)), &send)
+            {
+                log::warn!(
+                    "failed to collect targets for `{}`: {:#}",
+                    repo.path().display(),
+                    err
+                );
             }
+            Ok(())
+        });
+    });
 
-            let item = Arc::new(SkimGitTarget::new(t, args.preview_commit_details));
-            if let Err(e) = send.send(item) {
-                log::error!("unable to send item for selection: {}", e);
-            }
+    let (action, selected) = match select_targets(args, recv) {
+        Some(s) => s,
+        None => {
+            log::warn!("no selection was made");
+            return Ok(());
         }
-        recv
     };
-    let target = match select_and_return_first(args, recv) {
+    let target = match selected.first() {
         Some(t) => t,
         None => {
             log::warn!("no selection was made");
             return Ok(());
         }
     };
-    log::debug!("{:#?}", target);
+    log::debug!("{:?} {:#?}", action, target);
+
+    let mut repo = git2::Repository::open(&target.repo_path).context("git")?;
+    if action != JumpAction::Checkout {
+        return run_action(&mut repo, action, target);
+    }
+
+    if let Some(worktree) = find_branch_worktree(&repo, target) {
+        println!("{}", worktree.display());
+        return Ok(());
+    }
+    run_action(&mut repo, action, target)?;
+    println!("{}", git::repo_root(&repo).display());
+    Ok(())
+}
+
+/// Linked worktree that already has the target's local branch checked out.
+fn find_branch_worktree(repo: &git2::Repository, target: &GitTarget) -> Option<std::path::PathBuf> {
+    let branch = target
+        .branches
+        .first()
+        .filter(|b| b.branch_type == git2::BranchType::Local)?;
+    let main_repo = worktree::resolve_main_repo_path(git::repo_root(repo)).ok()?;
+    let linked = worktree::list_linked_worktree_details(&main_repo).ok()?;
+    linked
+        .into_iter()
+        .find(|wt| wt.branch_ref.as_deref() == Some(branch.ref_name.as_str()))
+        .map(|wt| wt.path)
+}
+
+fn send_repo_targets(
+    args: &argparse::GitJump,
+    repo: &git2::Repository,
+    project: Option<&Arc<Project>>,
+    send: &SkimItemSender,
+) -> anyhow::Result<()> {
+    let name = if args.use_author {
+        configured_author(repo)?
+    } else {
+        None
+    };
+
+    let targets = build_targets(repo, args.show_all_branches, TargetSources::from_args(args))?;
+
+    let primary = primary_commit(repo);
+
+    for mut t in targets {
+        if args.use_author && Some(t.commit.author.as_str()) != name.as_deref() {
+            log::trace!("skipping commit authored by {}", t.commit.author);
+            continue;
+        }
+
+        if let Some(primary) = primary {
+            mark_merge_state(repo, primary, &mut t);
+        }
+
+        if args.delete_merged && !is_bulk_delete_candidate(&t) {
+            continue;
+        }
+
+        let item = Arc::new(SkimGitTarget::new(
+            t,
+            args.preview_commit_details,
+            project.cloned(),
+        ));
+        if let Err(e) = send.send(item) {
+            anyhow::bail!("unable to send item for selection: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn run_action(
+    repo: &mut git2::Repository,
+    action: JumpAction,
+    target: &GitTarget,
+) -> anyhow::Result<()> {
+    match action {
+        JumpAction::Checkout => match &target.stash {
+            Some(stash) if target.branches.is_empty() => apply_stash(repo, stash),
+            _ => checkout_target(repo, target),
+        },
+        JumpAction::DeleteBranch => delete_target_branch(repo, target, false),
+        JumpAction::DeleteWithRemote => delete_target_branch(repo, target, true),
+        JumpAction::Rename => rename_target_branch(repo, target),
+        JumpAction::CopyName => copy_target_name(target),
+        JumpAction::NewBranch => new_branch_from_target(repo, target),
+    }
+}
+
+/// What to do with the highlighted target once skim exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JumpAction {
+    Checkout,
+    DeleteBranch,
+    DeleteWithRemote,
+    Rename,
+    CopyName,
+    NewBranch,
+}
+
+/// Keys passed to skim's `--expect`, with the action they trigger and a label for the header.
+const ACTION_BINDINGS: &[(&str, JumpAction, &str)] = &[
+    ("ctrl-d", JumpAction::DeleteBranch, "delete"),
+    ("alt-d", JumpAction::DeleteWithRemote, "delete+remote"),
+    ("alt-r", JumpAction::Rename, "rename"),
+    ("alt-y", JumpAction::CopyName, "copy name"),
+    ("alt-n", JumpAction::NewBranch, "new branch"),
+];
+
+/// Clipboard programs tried in order until one accepts the text.
+const CLIPBOARD_COMMANDS: &[&[&str]] = &[
+    &["pbcopy"],
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+];
+
+impl JumpAction {
+    fn from_accept_key(key: Option<&str>) -> JumpAction {
+        key.and_then(|key| {
+            ACTION_BINDINGS
+                .iter()
+                .find(|(k, _, _)| *k == key)
+                .map(|(_, action, _)| *action)
+        })
+        .unwrap_or(JumpAction::Checkout)
+    }
+}
+
+fn action_header() -> String {
+    let mut parts = vec!["enter: checkout".to_string()];
+    parts.extend(
+        ACTION_BINDINGS
+            .iter()
+            .map(|(key, _, label)| format!("{}: {}", key, label)),
+    );
+    parts.join(" | ")
+}
+
+fn is_bulk_delete_candidate(target: &GitTarget) -> bool {
+    target.is_merged
+        && !target.is_primary
+        && target
+            .branches
+            .iter()
+            .any(|b| b.branch_type == git2::BranchType::Local && !b.head)
+}
+
+fn delete_merged_targets(repo: &git2::Repository, targets: &[GitTarget]) -> anyhow::Result<()> {
+    let names = targets
+        .iter()
+        .flat_map(|t| t.branches.iter())
+        .filter(|b| b.branch_type == git2::BranchType::Local && !b.head)
+        .map(|b| b.name.as_str())
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        log::warn!("no local branches selected");
+        return Ok(());
+    }
+
+    eprintln!("branches to delete:");
+    for name in &names {
+        eprintln!("  {}", name);
+    }
+    if !prompt::confirm(&format!("delete {} merged branches?", names.len()))? {
+        log::warn!("delete aborted");
+        return Ok(());
+    }
+
+    let mut failures = Vec::new();
+    for name in names {
+        match git::delete_local_branch(repo, name) {
+            Ok(()) => println!("{}", name),
+            Err(err) => failures.push(format!("{}: {:#}", name, err)),
+        }
+    }
+    if !failures.is_empty() {
+        anyhow::bail!("failed to delete some branches:\n{}", failures.join("\n"));
+    }
+    Ok(())
+}
+
+fn delete_target_branch(
+    repo: &git2::Repository,
+    target: &GitTarget,
+    with_remote: bool,
+) -> anyhow::Result<()> {
+    let choices = branch_choices(repo, target, false)?;
+    if choices.is_empty() {
+        anyhow::bail!("selected commit has no branch to delete");
+    }
+    let Some(branch) = choose_branch(&choices, "delete")? else {
+        log::warn!("delete aborted");
+        return Ok(());
+    };
+
+    let mut remote_ref = match branch.branch_type {
+        git2::BranchType::Local => None,
+        git2::BranchType::Remote => Some(branch.ref_name.clone()),
+    };
+    if with_remote && branch.branch_type == git2::BranchType::Local {
+        remote_ref = git::local_branch_upstream(repo, &branch.name);
+        if remote_ref.is_none() {
+            log::warn!("`{}` has no upstream, only deleting locally", branch.name);
+        }
+    }
+    let remote = remote_ref
+        .as_deref()
+        .map(|r| git::split_remote_branch(repo, r))
+        .transpose()?;
+
+    let mut description = Vec::new();
+    if branch.branch_type == git2::BranchType::Local {
+        description.push(format!("local branch `{}`", branch.name));
+    }
+    if let Some((remote, name)) = &remote {
+        description.push(format!("branch `{}` on remote `{}`", name, remote));
+    }
+    if !target.is_merged {
+        eprintln!(
+            "warning: `{}` is not merged into the primary branch",
+            branch.name
+        );
+    }
+    if !prompt::confirm(&format!("delete {}?", description.join(" and ")))? {
+        log::warn!("delete aborted");
+        return Ok(());
+    }
+
+    if branch.branch_type == git2::BranchType::Local {
+        git::delete_local_branch(repo, &branch.name)?;
+    }
+    if let Some((remote, name)) = &remote {
+        git::delete_remote_branch(git::repo_root(repo), remote, name)?;
+    }
+    println!("{}", branch.name);
+    Ok(())
+}
+
+fn rename_target_branch(repo: &git2::Repository, target: &GitTarget) -> anyhow::Result<()> {
+    let choices = branch_choices(repo, target, true)?;
+    if choices.is_empty() {
+        anyhow::bail!("selected commit has no local branch to rename");
+    }
+    let Some(branch) = choose_branch(&choices, "rename")? else {
+        log::warn!("rename aborted");
+        return Ok(());
+    };
+    let new_name = prompt::read_line(&format!("rename `{}` to: ", branch.name))?;
+    if new_name.is_empty() {
+        log::warn!("rename aborted");
+        return Ok(());
+    }
 
-    checkout_target(&repo, &target)?;
+    let mut local = repo
+        .find_branch(&branch.name, git2::BranchType::Local)
+        .context("could not get branch by name")?;
+    local
+        .rename(&new_name, false)
+        .with_context(|| format!("could not rename `{}` to `{}`", branch.name, new_name))?;
+    println!("{}", new_name);
+    Ok(())
+}
 
+/// Branches at the commit of `target` an action can apply to: the local ones, or the remote
+/// ones when there is no local branch and `local_only` is not set.
+///
+/// The picker row may show only one branch, so they are looked up again in the repo.
+fn branch_choices(
+    repo: &git2::Repository,
+    target: &GitTarget,
+    local_only: bool,
+) -> anyhow::Result<Vec<GitBranch>> {
+    let branches = build_targets(repo, true, TargetSources::default())?
+        .into_iter()
+        .find(|t| t.commit.id == target.commit.id)
+        .map(|t| t.branches)
+        .unwrap_or_default();
+    let (local, remote): (Vec<_>, Vec<_>) = branches
+        .into_iter()
+        .partition(|b| b.branch_type == git2::BranchType::Local);
+    if local.is_empty() && !local_only {
+        return Ok(remote);
+    }
+    Ok(local)
+}
+
+/// The only choice, or the one the user picks when several branches point at the commit.
+fn choose_branch<'c>(
+    choices: &'c [GitBranch],
+    action: &str,
+) -> anyhow::Result<Option<&'c GitBranch>> {
+    if let [branch] = choices {
+        return Ok(Some(branch));
+    }
+    let names = choices.iter().map(|b| b.name.as_str()).collect::<Vec<_>>();
+    let picked = prompt::choose(
+        &format!(
+            "{} branches point at this commit, {} which?",
+            names.len(),
+            action
+        ),
+        &names,
+    )?;
+    Ok(picked.map(|index| &choices[index]))
+}
+
+fn copy_target_name(target: &GitTarget) -> anyhow::Result<()> {
+    let text = target
+        .branches
+        .first()
+        .map(|b| b.name.clone())
+        .unwrap_or_else(|| target.commit.id.to_string());
+
+    for command in CLIPBOARD_COMMANDS {
+        if copy_with(command, &text).is_ok() {
+            log::debug!("copied `{}` with `{}`", text, command[0]);
+            return Ok(());
+        }
+    }
+    log::warn!("no clipboard program available, printing instead");
+    println!("{}", text);
     Ok(())
 }
 
+fn copy_with(command: &[&str], text: &str) -> anyhow::Result<()> {
+    let mut child = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("could not spawn `{}`", command[0]))?;
+    child
+        .stdin
+        .take()
+        .context("clipboard stdin was not captured")?
+        .write_all(text.as_bytes())
+        .context("could not write to clipboard")?;
+    let status = child.wait().context("clipboard program failed")?;
+    if !status.success() {
+        anyhow::bail!("`{}` exited with {}", command[0], status);
+    }
+    Ok(())
+}
+
+fn new_branch_from_target(repo: &git2::Repository, target: &GitTarget) -> anyhow::Result<()> {
+    let new_name = prompt::read_line(&format!(
+        "new branch at {}: ",
+        &target.commit.id.to_string()[..7]
+    ))?;
+    if new_name.is_empty() {
+        log::warn!("new branch aborted");
+        return Ok(());
+    }
+
+    let commit = repo
+        .find_commit(target.commit.id)
+        .context("could not get commit from hash")?;
+    let branch = repo
+        .branch(&new_name, &commit, false)
+        .with_context(|| format!("could not create branch `{}`", new_name))?;
+    let new_target = GitTarget {
+        branches: vec![GitBranch {
+            name: new_name,
+            ref_name: GitRef::from(branch).to_string(),
+            branch_type: git2::BranchType::Local,
+            head: false,
+            upstream: None,
+        }],
+        ..target.clone()
+    };
+    checkout_target(repo, &new_target)
+}
+
 fn checkout_target(repo: &git2::Repository, target: &GitTarget) -> anyhow::Result<()> {
     if let Some(b) = target.branches.first() {
         log::debug!("checkout branch: {:?}", b.name);
@@ -335,11 +1039,22 @@ fn checkout_target(repo: &git2::Repository, target: &GitTarget) -> anyhow::Resul
         .find_object(target.commit.id, Some(git2::ObjectType::Commit))
         .context("could not get commit from hash")?;
     repo.checkout_tree(&o, None).context("checkout failed")?;
+    repo.set_head_detached(target.commit.id)
+        .context("could not detach head at commit")?;
 
     Ok(())
 }
 
-fn select_and_return_first(args: &argparse::GitJump, recv: SkimItemReceiver) -> Option<GitTarget> {
+fn apply_stash(repo: &mut git2::Repository, stash: &GitStash) -> anyhow::Result<()> {
+    log::debug!("apply stash@{{{}}}: {:?}", stash.index, stash.message);
+    repo.stash_apply(stash.index, None)
+        .with_context(|| format!("could not apply stash@{{{}}}", stash.index))
+}
+
+fn select_targets(
+    args: &argparse::GitJump,
+    recv: SkimItemReceiver,
+) -> Option<(JumpAction, Vec<GitTarget>)> {
     let width_ok = if args.disable_preview {
         None
     } else {
@@ -351,22 +1066,225 @@ fn select_and_return_first(args: &argparse::GitJump, recv: SkimItemReceiver) ->
             }
         })
     };
+    let header = if args.delete_merged {
+        "tab: select | enter: delete selected branches".to_string()
+    } else {
+        action_header()
+    };
+    let expect = ACTION_BINDINGS
+        .iter()
+        .map(|(key, _, _)| *key)
+        .collect::<Vec<_>>()
+        .join(",");
     let options = SkimOptionsBuilder::default()
         // .height(Some("50%"))
-        .multi(false)
+        .multi(args.delete_merged)
         .preview(width_ok)
+        .header(Some(&header))
+        .expect((!args.delete_merged).then_some(expect))
         .build()
         .unwrap();
 
     let result = Skim::run_with(&options, Some(recv))?;
     if result.is_abort {
-        None
-    } else {
-        result
-            .selected_items
-            .first()?
-            .as_any()
-            .downcast_ref::<SkimGitTarget>()
-            .map(|s| s.inner.clone())
+        return None;
+    }
+    let action = match &result.final_event {
+        Event::EvActAccept(key) => JumpAction::from_accept_key(key.as_deref()),
+        _ => JumpAction::Checkout,
+    };
+    let selected = result
+        .selected_items
+        .iter()
+        .filter_map(|item| item.as_any().downcast_ref::<SkimGitTarget>())
+        .map(|s| s.inner.clone())
+        .collect::<Vec<_>>();
+    Some((action, selected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::git::test_util;
+
+    #[test]
+    fn build_targets_includes_opt_in_sources() {
+        let path = test_util::unique_temp_path("gitjump-sources");
+        let repo = test_util::init_repo(&path);
+        {
+            let first = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.tag_lightweight("v1.0.0", first.as_object(), false)
+                .unwrap();
+            let sig = repo.signature().unwrap();
+            repo.tag("v1.0.1", first.as_object(), &sig, "release", false)
+                .unwrap();
+            test_util::commit_file(&repo, "second.txt", "two\n", "second commit");
+        }
+
+        let branches_only = build_targets(&repo, false, TargetSources::default()).expect("targets");
+        assert!(branches_only.iter().all(|t| t.tags.is_empty()));
+        assert_eq!(branches_only.len(), 1);
+
+        let sources = TargetSources {
+            tags: true,
+            stashes: true,
+            reflog: 10,
+        };
+        let targets = build_targets(&repo, false, sources).expect("targets");
+        assert_eq!(targets.len(), 2);
+        let tagged = targets
+            .iter()
+            .find(|t| !t.tags.is_empty())
+            .expect("tagged target");
+        assert_eq!(
+            tagged.tags,
+            vec![
+                GitTag {
+                    name: "v1.0.0".to_string(),
+                    annotated: false
+                },
+                GitTag {
+                    name: "v1.0.1".to_string(),
+                    annotated: true
+                },
+            ]
+        );
+        assert_eq!(tagged.reflog.as_ref().map(|r| r.index), Some(1));
+        assert!(tagged.branches.is_empty());
+
+        let line = format!(
+            "{}",
+            DisplayLine {
+                target: tagged,
+                collapse_pushed: true,
+            }
+        );
+        assert!(line.contains(TAG_ICON));
+        assert!(line.contains("HEAD@{1}"));
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn branch_choices_include_branches_hidden_from_the_row() {
+        let path = test_util::unique_temp_path("gitjump-branch-choices");
+        let repo = test_util::init_repo(&path);
+        {
+            let first = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.branch("feature", &first, false).unwrap();
+            repo.branch("fix", &first, false).unwrap();
+            let side = test_util::commit_file(&repo, "side.txt", "side\n", "side");
+            repo.reference("refs/remotes/origin/side", side, false, "test")
+                .unwrap();
+            test_util::commit_file(&repo, "main.txt", "main\n", "main");
+        }
+        let targets = build_targets(&repo, false, TargetSources::default()).expect("targets");
+        let names = |local_only| {
+            let target = targets
+                .iter()
+                .find(|t| t.branches[0].name == "feature")
+                .expect("feature target");
+            branch_choices(&repo, target, local_only)
+                .expect("choices")
+                .into_iter()
+                .map(|b| b.name)
+                .collect::<Vec<_>>()
+        };
+        let remote_only = targets
+            .iter()
+            .find(|t| t.branches[0].branch_type == git2::BranchType::Remote)
+            .expect("remote target");
+
+        assert_eq!(names(true), vec!["feature", "fix"]);
+        assert_eq!(
+            branch_choices(&repo, remote_only, false)
+                .expect("choices")
+                .into_iter()
+                .map(|b| b.name)
+                .collect::<Vec<_>>(),
+            vec!["origin/side"]
+        );
+        assert!(branch_choices(&repo, remote_only, true)
+            .expect("choices")
+            .is_empty());
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn project_title_prefixes_multi_repo_rows() {
+        let path = test_util::unique_temp_path("gitjump-project-prefix");
+        let repo = test_util::init_repo(&path);
+        let target = build_targets(&repo, false, TargetSources::default())
+            .expect("targets")
+            .remove(0);
+        let project = Arc::new(Project {
+            path: path.clone(),
+            typename: "work".to_string(),
+            title: "demo".to_string(),
+            worktree: None,
+            project_color: None,
+        });
+
+        let item = SkimGitTarget::new(target, false, Some(project));
+
+        assert!(item.text().starts_with("[demo] "));
+        assert!(item.display_str.stripped().starts_with("[demo] "));
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn upstream_markers_render_counts_and_gone() {
+        let strip = |text: String| skim::AnsiString::parse(&text).stripped().to_string();
+
+        assert_eq!(
+            strip(
+                DisplayUpstream(Some(UpstreamState::Tracking {
+                    ahead: 3,
+                    behind: 1
+                }))
+                .to_string()
+            ),
+            " ↑3 ↓1"
+        );
+        assert_eq!(
+            strip(
+                DisplayUpstream(Some(UpstreamState::Tracking {
+                    ahead: 0,
+                    behind: 0
+                }))
+                .to_string()
+            ),
+            ""
+        );
+        assert_eq!(
+            strip(DisplayUpstream(Some(UpstreamState::Gone)).to_string()),
+            " gone"
+        );
+        assert_eq!(DisplayUpstream(None).to_string(), "");
+    }
+
+    #[test]
+    fn accept_key_maps_to_action() {
+        assert_eq!(JumpAction::from_accept_key(None), JumpAction::Checkout);
+        assert_eq!(
+            JumpAction::from_accept_key(Some("ctrl-d")),
+            JumpAction::DeleteBranch
+        );
+        assert_eq!(
+            JumpAction::from_accept_key(Some("alt-n")),
+            JumpAction::NewBranch
+        );
+        assert_eq!(
+            JumpAction::from_accept_key(Some("ctrl-q")),
+            JumpAction::Checkout
+        );
+    }
+
+    #[test]
+    fn action_header_lists_every_binding() {
+        let header = action_header();
+        for (key, _, label) in ACTION_BINDINGS {
+            assert!(header.contains(&format!("{}: {}", key, label)));
+        }
     }
 }
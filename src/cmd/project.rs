@@ -3,7 +3,7 @@ use std::{
     sync::Arc,
 };
 
-use project_dir::Project;
+pub(crate) use project_dir::Project;
 use skim::{prelude::SkimOptionsBuilder, Skim, SkimItemReceiver, SkimItemSender};
 
 use self::project_dir::ProjectExtractor;
@@ -18,6 +18,7 @@ use crate::{
 mod project_dir;
 
 type ProjectQueue = VecDeque<(ProjectGroup, Option<Arc<Project>>)>;
+pub(crate) type ProjectSink<'a> = dyn FnMut(Project) -> anyhow::Result<()> + 'a;
 
 pub fn dirs(args: &argparse::ProjectDirs) -> anyhow::Result<()> {
     let mut groups = Vec::new();
@@ -30,6 +31,8 @@ pub fn dirs(args: &argparse::ProjectDirs) -> anyhow::Result<()> {
             extract: format!("{}/(.*)", path_text),
             color: None,
             recurse: args.git_recurse,
+            worktree_root: None,
+            worktree_setup: None,
         });
     }
     let project = search(groups, Vec::new())?;
@@ -54,30 +57,22 @@ fn update_tmux_and_display_results(
 }
 
 fn tmux_window_name(project: &Project) -> String {
-    if let Some(worktree) = &project.worktree {
-        format!("{}({})", project.title, worktree.name)
-    } else {
-        project.title.clone()
-    }
+    project.label()
 }
 
 fn search(groups: Vec<ProjectGroup>, directories: Vec<ManualDirectory>) -> anyhow::Result<Project> {
     log::debug!("groups: {:#?}", groups);
     log::debug!("manual directories: {:#?}", directories);
 
-    let mut queue: ProjectQueue = VecDeque::new();
-    for root in groups {
-        queue.push_back((root, None))
-    }
-
     let (send, recv): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
     std::thread::spawn(move || {
-        if let Err(err) = send_manual_directories(&send, &directories) {
-            log::error!("failed to send manual directories: {}", err);
-        }
-        if let Err(err) = scan_groups(queue, send) {
-            log::error!("failed to scan project groups: {}", err);
-        }
+        scan_projects(groups, directories, &mut |proj| {
+            let proj = Arc::new(proj);
+            if let Err(e) = send.send(proj.clone()) {
+                anyhow::bail!("channel send failure for `{:?}`: {}", proj.path, e);
+            }
+            Ok(())
+        });
     });
     let resp = select_and_return_first(recv);
 
@@ -88,8 +83,29 @@ fn search(groups: Vec<ProjectGroup>, directories: Vec<ManualDirectory>) -> anyho
     anyhow::bail!("no item was selected");
 }
 
+/// Discover manual directories, then scan every project group, including linked worktrees.
+///
+/// Each project is handed to `emit` as soon as it is found, so callers can stream results.
+pub(crate) fn scan_projects(
+    groups: Vec<ProjectGroup>,
+    directories: Vec<ManualDirectory>,
+    emit: &mut ProjectSink<'_>,
+) {
+    let mut queue: ProjectQueue = VecDeque::new();
+    for root in groups {
+        queue.push_back((root, None))
+    }
+
+    if let Err(err) = send_manual_directories(emit, &directories) {
+        log::error!("failed to send manual directories: {}", err);
+    }
+    if let Err(err) = scan_groups(queue, emit) {
+        log::error!("failed to scan project groups: {}", err);
+    }
+}
+
 fn send_manual_directories(
-    send: &SkimItemSender,
+    emit: &mut ProjectSink<'_>,
     directories: &[ManualDirectory],
 ) -> anyhow::Result<()> {
     let mut sent_paths: HashSet<std::path::PathBuf> = HashSet::new();
@@ -100,15 +116,15 @@ fn send_manual_directories(
         );
         let (project, is_linked_worktree) =
             annotate_worktree_metadata(&manual_directory.path, project);
-        send_project_if_new(send, &mut sent_paths, project.clone())?;
+        send_project_if_new(emit, &mut sent_paths, project.clone())?;
         if !is_linked_worktree {
-            send_linked_worktree_projects(send, &mut sent_paths, &manual_directory.path, &project)?;
+            send_linked_worktree_projects(emit, &mut sent_paths, &manual_directory.path, &project)?;
         }
     }
     Ok(())
 }
 
-fn scan_groups(mut queue: ProjectQueue, send: SkimItemSender) -> anyhow::Result<()> {
+fn scan_groups(mut queue: ProjectQueue, emit: &mut ProjectSink<'_>) -> anyhow::Result<()> {
     let default_config = ProjectGroup {
         root: "".into(),
         exclude: Vec::new(),
@@ -116,6 +132,8 @@ fn scan_groups(mut queue: ProjectQueue, send: SkimItemSender) -> anyhow::Result<
         extract: "(.*)".to_string(),
         color: None,
         recurse: false,
+        worktree_root: None,
+        worktree_setup: None,
     };
     let default_extract = ProjectExtractor::new(&default_config).expect("bad config");
     let mut sent_paths: HashSet<std::path::PathBuf> = HashSet::new();
@@ -135,9 +153,9 @@ fn scan_groups(mut queue: ProjectQueue, send: SkimItemSender) -> anyhow::Result<
                 });
 
             let (proj, is_linked_worktree) = annotate_worktree_metadata(&repo_path, proj);
-            send_project_if_new(&send, &mut sent_paths, proj.clone())?;
+            send_project_if_new(emit, &mut sent_paths, proj.clone())?;
             if !is_linked_worktree {
-                send_linked_worktree_projects(&send, &mut sent_paths, &repo_path, &proj)?;
+                send_linked_worktree_projects(emit, &mut sent_paths, &repo_path, &proj)?;
             }
 
             // println!("{:?}", x);
@@ -172,22 +190,18 @@ fn annotate_worktree_metadata(repo_path: &std::path::Path, proj: Project) -> (Pr
 }
 
 fn send_project_if_new(
-    send: &SkimItemSender,
+    emit: &mut ProjectSink<'_>,
     sent_paths: &mut HashSet<std::path::PathBuf>,
     proj: Project,
 ) -> anyhow::Result<()> {
     if !sent_paths.insert(proj.path.clone()) {
         return Ok(());
     }
-    let proj = Arc::new(proj);
-    if let Err(e) = send.send(proj.clone()) {
-        anyhow::bail!("channel send failure for `{:?}`: {}", proj.path, e);
-    }
-    Ok(())
+    emit(proj)
 }
 
 fn send_linked_worktree_projects(
-    send: &SkimItemSender,
+    emit: &mut ProjectSink<'_>,
     sent_paths: &mut HashSet<std::path::PathBuf>,
     repo_path: &std::path::Path,
     proj: &Project,
@@ -212,7 +226,7 @@ fn send_linked_worktree_projects(
             worktree: Some(project_dir::WorktreeProjectMetadata { name: linked.name }),
             project_color: proj.project_color,
         };
-        send_project_if_new(send, sent_paths, worktree_project)?;
+        send_project_if_new(emit, sent_paths, worktree_project)?;
     }
     Ok(())
 }
@@ -237,7 +251,7 @@ fn select_and_return_first(recv: SkimItemReceiver) -> Option<Project> {
     }
 }
 
-fn rename_tmux_default_window(
+pub(crate) fn rename_tmux_default_window(
     name: &str,
     tmux_rename: &argparse::TmuxRename,
 ) -> anyhow::Result<()> {
@@ -120,6 +120,14 @@ impl Project {
         }
     }
 
+    /// Title, followed by the worktree name for linked worktrees, e.g. `api(feature-123)`.
+    pub fn label(&self) -> String {
+        match &self.worktree {
+            Some(worktree) => format!("{}({})", self.title, worktree.name),
+            None => self.title.clone(),
+        }
+    }
+
     pub fn with_worktree_metadata(mut self, metadata: Option<WorktreeProjectMetadata>) -> Self {
         self.worktree = metadata;
         self
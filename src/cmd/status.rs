@@ -0,0 +1,310 @@
+use std::{fmt, io::IsTerminal, path::PathBuf};
+
+use ansi_term::Style;
+use anyhow::Context;
+use serde::Serialize;
+
+use crate::{
+    argparse,
+    cmd::project::{scan_projects, Project},
+    config::{load_config, NamedColor},
+    git,
+    parallel::parallel_map,
+    skim_style,
+};
+
+/// Checkouts inspected at once; status walks the work tree, so this is mostly I/O bound.
+const MAX_WORKERS: usize = 8;
+
+/// Some checkouts need attention; main exits with status 1 without reporting a failure.
+#[derive(Debug)]
+pub struct NeedsAttention;
+
+impl fmt::Display for NeedsAttention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("checkouts need attention")
+    }
+}
+
+impl std::error::Error for NeedsAttention {}
+
+#[derive(Debug, Clone, Serialize)]
+struct CheckoutStatus {
+    group: String,
+    title: String,
+    worktree: Option<String>,
+    path: PathBuf,
+    branch: Option<String>,
+    detached: bool,
+    #[serde(flatten)]
+    unsaved: git::UnsavedWork,
+    /// Rebase, merge or other operation that was started and not finished
+    operation: Option<&'static str>,
+    #[serde(skip)]
+    color: Option<NamedColor>,
+}
+
+impl CheckoutStatus {
+    fn inspect(project: &Project) -> anyhow::Result<CheckoutStatus> {
+        let repo = git2::Repository::open(&project.path)
+            .with_context(|| format!("failed to open `{}`", project.path.display()))?;
+        let mut unsaved = git::inspect_unsaved_work(&project.path)?;
+        if repo
+            .remotes()
+            .map(|remotes| remotes.is_empty())
+            .unwrap_or(true)
+        {
+            // a repo without remotes has nowhere to push, so every commit would count
+            unsaved.unpushed = 0;
+        }
+        let head = repo.head().ok();
+        let branch = head
+            .as_ref()
+            .filter(|head| head.is_branch())
+            .and_then(|head| head.shorthand())
+            .map(ToOwned::to_owned);
+
+        Ok(CheckoutStatus {
+            group: project.typename.clone(),
+            title: project.title.clone(),
+            worktree: project.worktree.as_ref().map(|wt| wt.name.clone()),
+            path: project.path.clone(),
+            branch,
+            detached: repo.head_detached().unwrap_or(false),
+            unsaved,
+            operation: operation_name(repo.state()),
+            color: project.project_color,
+        })
+    }
+
+    fn needs_attention(&self) -> bool {
+        self.detached || self.operation.is_some() || !self.unsaved.is_empty()
+    }
+
+    fn name(&self) -> String {
+        match &self.worktree {
+            Some(worktree) => format!("{}({})", self.title, worktree),
+            None => self.title.clone(),
+        }
+    }
+
+    /// What needs attention, most urgent first, and the style to show it in.
+    fn state(&self) -> (String, Style) {
+        let mut parts = Vec::new();
+        if let Some(operation) = self.operation {
+            parts.push(format!("{} in progress", operation));
+        } else if self.detached {
+            // a rebase detaches HEAD anyway, so this is only worth saying on its own
+            parts.push("detached HEAD".to_string());
+        }
+        if !self.unsaved.is_empty() {
+            parts.push(self.unsaved.describe());
+        }
+
+        let style = if self.operation.is_some() {
+            skim_style::dirty_style()
+        } else if !self.unsaved.is_empty() {
+            skim_style::unsaved_work_style()
+        } else if self.detached {
+            skim_style::detached_style()
+        } else {
+            return ("clean".to_string(), skim_style::clean_style());
+        };
+        (parts.join(", "), style)
+    }
+}
+
+pub fn status(args: &argparse::Status) -> anyhow::Result<()> {
+    let config = load_config(args.config.as_deref())?;
+    let mut projects = Vec::new();
+    scan_projects(config.projects, config.directories, &mut |project| {
+        projects.push(project);
+        Ok(())
+    });
+
+    let statuses = inspect_projects(&projects);
+    let attention = statuses
+        .iter()
+        .filter(|status| status.needs_attention())
+        .count();
+    let shown = statuses
+        .iter()
+        .filter(|status| args.all || status.needs_attention())
+        .collect::<Vec<_>>();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&shown)?);
+    } else {
+        print!("{}", render_table(&shown, std::io::stdout().is_terminal()));
+    }
+
+    if attention == 0 {
+        eprintln!("{} checkouts, nothing needs attention", statuses.len());
+        return Ok(());
+    }
+    eprintln!(
+        "{} of {} checkouts need attention",
+        attention,
+        statuses.len()
+    );
+    Err(NeedsAttention.into())
+}
+
+/// Status of each project, in the same order, inspected on a few threads.
+///
+/// Projects that cannot be inspected are left out with a warning.
+fn inspect_projects(projects: &[Project]) -> Vec<CheckoutStatus> {
+    parallel_map(
+        projects,
+        MAX_WORKERS,
+        |project| match CheckoutStatus::inspect(project) {
+            Ok(status) => Some(status),
+            Err(err) => {
+                log::warn!("{:#}", err);
+                None
+            }
+        },
+    )
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Checkouts under a header per project group, in the order the groups were first found.
+fn render_table(statuses: &[&CheckoutStatus], color: bool) -> String {
+    let paint = |style: Style, text: String| {
+        if color {
+            style.paint(text).to_string()
+        } else {
+            text
+        }
+    };
+
+    let mut groups: Vec<&str> = Vec::new();
+    for status in statuses {
+        if !groups.contains(&status.group.as_str()) {
+            groups.push(&status.group);
+        }
+    }
+    let name_width = statuses
+        .iter()
+        .map(|status| status.name().chars().count())
+        .max()
+        .unwrap_or(0);
+    let branch_width = statuses
+        .iter()
+        .map(|status| status.branch.as_deref().unwrap_or("-").chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    for group in groups {
+        let mut members = statuses.iter().filter(|status| status.group == group);
+        let color_name = members.clone().next().and_then(|status| status.color);
+        out.push_str(&paint(
+            skim_style::project_tag_style(color_name),
+            group.to_string(),
+        ));
+        out.push('\n');
+        for status in &mut members {
+            let (state, state_style) = status.state();
+            let name = format!("{:<width$}", status.name(), width = name_width);
+            let name_style = skim_style::worktree_name_style(status.unsaved.is_dirty());
+            let branch = format!(
+                "{:<width$}",
+                status.branch.as_deref().unwrap_or("-"),
+                width = branch_width
+            );
+            out.push_str(&format!(
+                "  {}  {}  {}\n",
+                paint(name_style, name),
+                paint(skim_style::branch_style(), branch),
+                paint(state_style, state)
+            ));
+        }
+    }
+    out
+}
+
+fn operation_name(state: git2::RepositoryState) -> Option<&'static str> {
+    use git2::RepositoryState::*;
+    match state {
+        Clean => None,
+        Merge => Some("merge"),
+        Revert | RevertSequence => Some("revert"),
+        CherryPick | CherryPickSequence => Some("cherry-pick"),
+        Bisect => Some("bisect"),
+        Rebase | RebaseInteractive | RebaseMerge => Some("rebase"),
+        ApplyMailbox | ApplyMailboxOrRebase => Some("am"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_util;
+
+    fn project(group: &str, path: PathBuf) -> Project {
+        Project {
+            title: path.file_name().unwrap().to_string_lossy().to_string(),
+            path,
+            typename: group.to_string(),
+            worktree: None,
+            project_color: None,
+        }
+    }
+
+    #[test]
+    fn reports_unsaved_work_detached_heads_and_unfinished_merges() {
+        let root = test_util::unique_temp_path("status");
+        test_util::init_repo(&root.join("clean"));
+        test_util::init_repo(&root.join("dirty"));
+        std::fs::write(root.join("dirty/notes.txt"), "todo\n").unwrap();
+        let merging = test_util::init_repo(&root.join("merging"));
+        let head = merging.head().unwrap().target().unwrap();
+        std::fs::write(merging.path().join("MERGE_HEAD"), format!("{}\n", head)).unwrap();
+        let detached = test_util::init_repo(&root.join("detached"));
+        detached
+            .set_head_detached(detached.head().unwrap().target().unwrap())
+            .unwrap();
+
+        let projects = vec![
+            project("work", root.join("clean")),
+            project("oss", root.join("dirty")),
+            project("work", root.join("merging")),
+            project("work", root.join("detached")),
+            project("work", root.join("missing")),
+        ];
+        let statuses = inspect_projects(&projects);
+
+        assert_eq!(statuses.len(), 4);
+        let attention = statuses
+            .iter()
+            .map(|status| (status.title.as_str(), status.needs_attention()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            attention,
+            vec![
+                ("clean", false),
+                ("dirty", true),
+                ("merging", true),
+                ("detached", true)
+            ]
+        );
+        assert_eq!(statuses[2].operation, Some("merge"));
+
+        let table = render_table(&statuses.iter().collect::<Vec<_>>(), false);
+        assert_eq!(
+            table.lines().collect::<Vec<_>>(),
+            vec![
+                "work",
+                "  clean     main  clean",
+                "  merging   main  merge in progress",
+                "  detached  -     detached HEAD",
+                "oss",
+                "  dirty     main  1 untracked file",
+            ]
+        );
+        std::fs::remove_dir_all(&root).expect("cleanup repos");
+    }
+}
@@ -9,7 +9,100 @@ use std::{
 use anyhow::Context;
 use skim::{prelude::SkimOptionsBuilder, Skim, SkimItem, SkimItemReceiver, SkimItemSender};
 
-use crate::{argparse, config, skim_style, worktree};
+use crate::{
+    argparse,
+    cmd::{branch, gitjump, project},
+    config, git, hooks, prompt, skim_style, worktree,
+};
+
+mod doctor;
+mod du;
+mod list;
+mod manage;
+mod remote;
+mod sparse;
+mod transaction;
+
+pub use doctor::doctor;
+pub use du::du;
+pub use list::list;
+pub use manage::{lock, migrate, move_worktree, unlock};
+pub use sparse::sparse;
+use transaction::{CreateTransaction, Step};
+
+const KIB: f64 = 1024.0;
+const SIZE_UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// What `git worktree add` is asked to create, after resolving names and fetching refs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CreatePlan {
+    name: String,
+    /// Branch created with `-b`, or `None` for a detached worktree
+    branch: Option<String>,
+    commitish: Option<String>,
+    /// Set the new branch to track `commitish`, which is a remote-tracking branch
+    track: bool,
+    /// Check out `branch` as it is instead of creating it
+    existing_branch: bool,
+}
+
+impl CreatePlan {
+    fn from_args(args: &argparse::WorktreeCreate, name: String) -> CreatePlan {
+        let branch = if args.detach {
+            None
+        } else {
+            Some(args.branch.clone().unwrap_or_else(|| name.clone()))
+        };
+        CreatePlan {
+            name,
+            branch,
+            commitish: args.commitish.clone(),
+            track: false,
+            existing_branch: false,
+        }
+    }
+
+    /// Check out a branch picked with skim; a remote branch gets a local branch tracking it
+    /// unless one of the same name exists already, and `--detach` checks out its commit.
+    fn from_picked_branch(
+        repo: &git2::Repository,
+        picked: &git::GitBranch,
+        args: &argparse::WorktreeCreate,
+    ) -> anyhow::Result<CreatePlan> {
+        if args.detach {
+            return Ok(CreatePlan {
+                name: worktree_name_from_branch(&picked.name),
+                branch: None,
+                commitish: Some(picked.ref_name.clone()),
+                track: false,
+                existing_branch: false,
+            });
+        }
+        let (local, commitish) = match picked.branch_type {
+            git2::BranchType::Local => (picked.name.clone(), None),
+            git2::BranchType::Remote => {
+                let (_, branch) = git::split_remote_branch(repo, &picked.ref_name)?;
+                (branch, Some(picked.ref_name.clone()))
+            }
+        };
+        let existing_branch =
+            commitish.is_none() || repo.find_branch(&local, git2::BranchType::Local).is_ok();
+        if existing_branch && git::checked_out_branches(repo).contains(&local) {
+            anyhow::bail!("branch `{}` is already checked out", local);
+        }
+
+        Ok(CreatePlan {
+            name: worktree_name_from_branch(&local),
+            branch: Some(match &args.branch {
+                Some(branch) if !existing_branch => branch.clone(),
+                _ => local,
+            }),
+            commitish: commitish.filter(|_| !existing_branch),
+            track: !existing_branch,
+            existing_branch,
+        })
+    }
+}
 
 pub fn create(args: &argparse::WorktreeCreate) -> anyhow::Result<()> {
     let cwd = std::env::current_dir().context("failed to read current directory")?;
@@ -21,23 +114,115 @@ pub fn create(args: &argparse::WorktreeCreate) -> anyhow::Result<()> {
     let main_repo = worktree::resolve_main_repo_path(repo_workdir)
         .context("failed to resolve main repository path")?;
 
-    let worktree_root = worktree::resolve_worktree_root(args.config.as_deref())?;
-    let destination = worktree::build_worktree_destination(&main_repo, &args.name, &worktree_root)
+    let worktree_root = worktree::resolve_worktree_root(args.config.as_deref(), &main_repo)?;
+    let cfg = match config::load_config(args.config.as_deref()) {
+        Ok(cfg) => Some(cfg),
+        Err(err) => {
+            log::debug!("creating worktree without config: {:#}", err);
+            None
+        }
+    };
+    let sparse_dirs = args
+        .sparse
+        .as_deref()
+        .map(|profile| sparse::profile_dirs(cfg.as_ref(), profile))
+        .transpose()?;
+    let plan = if let Some(spec) = &args.from_remote {
+        remote::plan_remote_branch(&repo, spec, args)?
+    } else if let Some(ref_name) = &args.fetch_ref {
+        remote::plan_fetched_ref(&repo, ref_name, args)?
+    } else if let Some(name) = &args.name {
+        let mut plan = CreatePlan::from_args(args, name.clone());
+        if let Some(cfg) = &cfg {
+            apply_naming_templates(&mut plan, &cfg.worktrees, &repo, args.branch.is_none())?;
+        }
+        plan
+    } else {
+        let Some(branch) = gitjump::select_branch(&repo, "pick a branch for the new worktree")?
+        else {
+            log::warn!("no selection was made");
+            return Ok(());
+        };
+        let mut plan = CreatePlan::from_picked_branch(&repo, &branch, args)?;
+        plan.name = unique_worktree_name(&main_repo, &plan.name, &worktree_root)?;
+        plan
+    };
+    validate_worktree_name(&plan.name)?;
+    if let Some(branch) = &plan.branch {
+        validate_branch_name(branch)?;
+    }
+
+    let destination = worktree::build_worktree_destination(&main_repo, &plan.name, &worktree_root)
         .context("failed to build worktree destination")?;
 
-    ensure_destination_parent_exists(&destination)?;
     ensure_destination_missing(&destination)?;
 
-    run_git_worktree_add(&main_repo, &destination, args)?;
-
-    if let Ok(cfg) = config::load_config(args.config.as_deref()) {
-        symlink_setup_paths(&main_repo, &destination, &cfg.worktrees.setup);
+    let mut transaction = CreateTransaction::default();
+    if let Err(err) = create_steps(
+        &main_repo,
+        &destination,
+        &plan,
+        sparse_dirs.as_deref(),
+        cfg.as_ref(),
+        &mut transaction,
+    ) {
+        return Err(transaction.rollback(err).context(format!(
+            "failed to create worktree `{}`",
+            destination.display()
+        )));
     }
 
     println!("{}", destination.display());
     Ok(())
 }
 
+/// Everything `worktree create` does after planning, recorded in `transaction`.
+fn create_steps(
+    main_repo: &Path,
+    destination: &Path,
+    plan: &CreatePlan,
+    sparse_dirs: Option<&[String]>,
+    cfg: Option<&config::ShelfConfig>,
+    transaction: &mut CreateTransaction,
+) -> anyhow::Result<()> {
+    if let Some(slug_dir) = destination.parent() {
+        transaction.create_dir_all(slug_dir)?;
+        if let Err(err) = worktree::write_slug_metadata(slug_dir, main_repo) {
+            log::warn!("{:#}", err);
+        }
+    }
+
+    add_worktree_steps(main_repo, destination, plan, sparse_dirs, transaction)?;
+
+    let Some(cfg) = cfg else {
+        return Ok(());
+    };
+    let setup_ctx = worktree::setup::SetupContext {
+        main_repo,
+        destination,
+        worktree_name: &plan.name,
+        branch: plan.branch.as_deref(),
+    };
+    let mut created = Vec::new();
+    let setup = worktree::setup::apply_setup_entries(
+        &setup_ctx,
+        cfg.worktree_setup_for(main_repo),
+        &mut created,
+    );
+    for target in created {
+        transaction.record(Step::SetupEntry(target));
+    }
+    setup?;
+
+    let env = hooks::HookEnv {
+        main_repo: main_repo.to_path_buf(),
+        worktree: destination.to_path_buf(),
+        name: plan.name.clone(),
+        branch: plan.branch.clone(),
+    };
+    hooks::run_hooks("post-create", &cfg.worktrees.hooks.post_create, &env)
+}
+
 #[derive(Debug, Clone)]
 struct CleanupCandidate {
     path: std::path::PathBuf,
@@ -46,10 +231,14 @@ struct CleanupCandidate {
     branch: Option<String>,
     upstream: Option<String>,
     commit_message: Option<String>,
+    commit_time: Option<i64>,
     dirty: bool,
+    unsaved: git::UnsavedWork,
     detached: bool,
     locked: bool,
     prunable: bool,
+    /// Only measured for cleanup, where it helps pick what to remove
+    size: Option<worktree::DiskUsage>,
     display_str: skim::AnsiString<'static>,
 }
 
@@ -58,13 +247,14 @@ impl CleanupCandidate {
         details: worktree::LinkedWorktreeDetails,
         main_repo: &Path,
     ) -> CleanupCandidate {
-        Self::from_details_with_repo(details, None, main_repo)
+        Self::from_details_with_repo(details, None, main_repo, None)
     }
 
     fn from_details_with_repo(
         details: worktree::LinkedWorktreeDetails,
         repo_label: Option<&str>,
         main_repo: &Path,
+        size: Option<worktree::DiskUsage>,
     ) -> CleanupCandidate {
         let branch = details
             .branch_ref
@@ -72,8 +262,13 @@ impl CleanupCandidate {
             .and_then(|name| name.strip_prefix("refs/heads/"))
             .map(ToOwned::to_owned);
         let upstream = find_upstream_branch(&details.path, branch.as_deref());
-        let commit_message = find_head_commit_message(&details.path);
-        let dirty = is_worktree_dirty(&details.path);
+        let head_commit = find_head_commit(&details.path);
+        let commit_message = head_commit
+            .as_ref()
+            .and_then(|commit| commit_summary(&commit.message));
+        let commit_time = head_commit.map(|commit| commit.time.seconds());
+        let unsaved = find_unsaved_work(&details.path);
+        let dirty = unsaved.is_dirty();
 
         let display_name = match repo_label {
             Some(label) => format!("{}/{}", label, details.name),
@@ -105,6 +300,25 @@ impl CleanupCandidate {
             tags.push(tag.clone());
             styled_tags.push(skim_style::clean_style().paint(tag).to_string());
         }
+        if unsaved.unpushed > 0 {
+            let tag = format!("(unpushed {})", unsaved.unpushed);
+            tags.push(tag.clone());
+            styled_tags.push(skim_style::unsaved_work_style().paint(tag).to_string());
+        }
+        if unsaved.stashes > 0 {
+            let tag = format!("(stashes {})", unsaved.stashes);
+            tags.push(tag.clone());
+            styled_tags.push(skim_style::unsaved_work_style().paint(tag).to_string());
+        }
+        if let Some(size) = size {
+            let tag = format!(
+                "({}, {} on disk)",
+                format_size(size.apparent),
+                format_size(size.on_disk)
+            );
+            tags.push(tag.clone());
+            styled_tags.push(skim_style::size_style().paint(tag).to_string());
+        }
         if details.locked {
             let tag = "(locked)".to_string();
             tags.push(tag.clone());
@@ -144,10 +358,13 @@ impl CleanupCandidate {
             branch,
             upstream,
             commit_message,
+            commit_time,
             dirty,
+            unsaved,
             detached: details.detached,
             locked: details.locked,
             prunable: details.prunable,
+            size,
             display_str: skim::AnsiString::parse(&styled_text),
         }
     }
@@ -168,6 +385,12 @@ impl SkimItem for CleanupCandidate {
         if self.dirty {
             parts.push("dirty".to_string());
         }
+        if self.unsaved.unpushed > 0 {
+            parts.push("unpushed".to_string());
+        }
+        if self.unsaved.stashes > 0 {
+            parts.push("stashed".to_string());
+        }
         if self.detached {
             parts.push("detached".to_string());
         }
@@ -186,8 +409,20 @@ impl SkimItem for CleanupCandidate {
 }
 
 pub fn cleanup_all(args: &argparse::WorktreeCleanupAll) -> anyhow::Result<()> {
-    let worktree_root = worktree::resolve_worktree_root(args.config.as_deref())?;
-    let all_repos = worktree::discover_all_worktrees_from_root(&worktree_root)?;
+    let all_repos = worktree::discover_all_worktrees(args.config.as_deref())?;
+    let filter = CleanupFilter::from_args(args)?;
+
+    // only the picker shows sizes; with filters just the removed worktrees are measured, later
+    let sizes = if filter.is_active() {
+        Vec::new()
+    } else {
+        let paths = all_repos
+            .iter()
+            .flat_map(|(_, linked_worktrees)| linked_worktrees.iter().map(|d| d.path.clone()))
+            .collect::<Vec<_>>();
+        du::measure_worktrees(&paths)
+    };
+    let mut sizes = sizes.into_iter();
 
     let mut candidates: Vec<Arc<CleanupCandidate>> = Vec::new();
     for (main_repo, linked_worktrees) in all_repos {
@@ -201,6 +436,7 @@ pub fn cleanup_all(args: &argparse::WorktreeCleanupAll) -> anyhow::Result<()> {
                 details,
                 Some(&repo_label),
                 &main_repo,
+                sizes.next(),
             )));
         }
     }
@@ -209,33 +445,215 @@ pub fn cleanup_all(args: &argparse::WorktreeCleanupAll) -> anyhow::Result<()> {
         anyhow::bail!("no linked worktrees found under worktree root");
     }
 
-    let selected = select_worktrees_to_cleanup(candidates);
+    let options = CleanupOptions {
+        config: args.config.as_deref(),
+        force: args.force,
+        prompt_unsafe: !args.yes,
+        delete_branch: branch_deletion_flags(args.delete_branch, args.delete_remote),
+    };
+    if !filter.is_active() {
+        if args.dry_run || args.yes {
+            anyhow::bail!("--dry-run and --yes need at least one cleanup filter");
+        }
+        let selected = select_worktrees_to_cleanup(candidates);
+        if selected.is_empty() {
+            anyhow::bail!("no worktrees selected for cleanup");
+        }
+        return remove_selected_worktrees(selected, &options);
+    }
+
+    let mut selected = candidates
+        .iter()
+        .filter(|candidate| filter.matches(candidate))
+        .map(|candidate| candidate.as_ref().clone())
+        .collect::<Vec<_>>();
     if selected.is_empty() {
-        anyhow::bail!("no worktrees selected for cleanup");
+        log::warn!("no worktrees match the cleanup filters");
+        return Ok(());
     }
 
-    let mut failures = Vec::new();
-    for selected_worktree in &selected {
-        if let Err(err) =
-            run_git_worktree_remove(&selected_worktree.main_repo, &selected_worktree.path)
-        {
-            failures.push(format!("{}: {}", selected_worktree.path.display(), err));
-            continue;
+    if args.dry_run {
+        for candidate in &selected {
+            println!("{}", candidate.path.display());
         }
-        println!("{}", selected_worktree.path.display());
+        return Ok(());
     }
 
-    if !failures.is_empty() {
-        anyhow::bail!(
-            "failed to remove some selected worktrees:\n{}",
-            failures.join("\n")
-        );
+    if !args.yes {
+        eprintln!("these worktrees match the cleanup filters:");
+        for candidate in &selected {
+            eprintln!("  {}", candidate.path.display());
+        }
+        if !prompt::confirm(&format!("remove {} worktrees?", selected.len()))? {
+            log::warn!("cleanup aborted");
+            return Ok(());
+        }
+    }
+
+    let paths = selected
+        .iter()
+        .map(|candidate| candidate.path.clone())
+        .collect::<Vec<_>>();
+    for (candidate, size) in selected.iter_mut().zip(du::measure_worktrees(&paths)) {
+        candidate.size = Some(size);
+    }
+
+    remove_selected_worktrees(selected, &options)
+}
+
+/// Non-interactive selection for `cleanup-all`; a worktree has to pass every given filter.
+struct CleanupFilter {
+    merged: bool,
+    older_than: Option<chrono::Duration>,
+    prunable: bool,
+    clean_only: bool,
+    repo: Option<regex::Regex>,
+    now: i64,
+}
+
+impl CleanupFilter {
+    fn from_args(args: &argparse::WorktreeCleanupAll) -> anyhow::Result<CleanupFilter> {
+        let repo = args
+            .repo
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()
+            .context("invalid repo pattern")?;
+        Ok(CleanupFilter {
+            merged: args.merged,
+            older_than: args.older_than,
+            prunable: args.prunable,
+            clean_only: args.clean_only,
+            repo,
+            now: chrono::Utc::now().timestamp(),
+        })
+    }
+
+    fn is_active(&self) -> bool {
+        self.merged
+            || self.older_than.is_some()
+            || self.prunable
+            || self.clean_only
+            || self.repo.is_some()
+    }
+
+    fn matches(&self, candidate: &CleanupCandidate) -> bool {
+        if let Some(repo) = &self.repo {
+            if !repo.is_match(&candidate.main_repo.to_string_lossy()) {
+                return false;
+            }
+        }
+        if self.prunable && !candidate.prunable {
+            return false;
+        }
+        if self.clean_only && !candidate.unsaved.is_empty() {
+            return false;
+        }
+        if let Some(older_than) = self.older_than {
+            match candidate.commit_time {
+                Some(time) if self.now - time >= older_than.num_seconds() => {}
+                _ => return false,
+            }
+        }
+        if self.merged && !is_candidate_merged(candidate) {
+            return false;
+        }
+        true
     }
+}
 
+/// True when the worktree's branch is merged into the primary branch of its repository.
+fn is_candidate_merged(candidate: &CleanupCandidate) -> bool {
+    let Some(branch_name) = candidate.branch.as_deref() else {
+        return false;
+    };
+    let repo = match git2::Repository::open(&candidate.main_repo) {
+        Ok(repo) => repo,
+        Err(err) => {
+            log::debug!("failed to open `{:?}`: {}", candidate.main_repo, err);
+            return false;
+        }
+    };
+    let Some(primary) = gitjump::primary_commit(&repo) else {
+        log::debug!("`{:?}` has no `origin/HEAD`", candidate.main_repo);
+        return false;
+    };
+    matches!(
+        branch::prune_reason(&repo, Some(primary), branch_name),
+        Ok(Some(branch::PruneReason::Merged))
+    )
+}
+
+pub fn switch(args: &argparse::WorktreeSwitch) -> anyhow::Result<()> {
+    let cwd = std::env::current_dir().context("failed to read current directory")?;
+    let repo = git2::Repository::discover(&cwd).context("git")?;
+    let repo_workdir = repo
+        .workdir()
+        .context("bare repositories are not supported")?;
+    let main_repo = worktree::resolve_main_repo_path(repo_workdir)
+        .context("failed to resolve main repository path")?;
+
+    let mut candidates = Vec::new();
+    if main_repo != repo_workdir {
+        let main_details = main_checkout_details(&main_repo)?;
+        candidates.push(Arc::new(CleanupCandidate::from_details(
+            main_details,
+            &main_repo,
+        )));
+    }
+    candidates.extend(build_cleanup_candidates(&main_repo, repo_workdir, false)?);
+    if candidates.is_empty() {
+        anyhow::bail!("no other worktrees to switch to");
+    }
+
+    let Some(selected) = select_worktrees(candidates, false).into_iter().next() else {
+        anyhow::bail!("no worktree selected");
+    };
+
+    if let Some(tmux_rename) = &args.tmux_rename {
+        let window_name = switch_window_name(&main_repo, &selected);
+        project::rename_tmux_default_window(&window_name, tmux_rename)?;
+    }
+    println!("{}", selected.path.display());
     Ok(())
 }
 
-pub fn cleanup(_args: &argparse::WorktreeCleanup) -> anyhow::Result<()> {
+/// The main checkout in the shape of a linked worktree, so it can be listed next to them.
+fn main_checkout_details(main_repo: &Path) -> anyhow::Result<worktree::LinkedWorktreeDetails> {
+    let repo = git2::Repository::open(main_repo).context("git")?;
+    let detached = repo.head_detached().unwrap_or(false);
+    let branch_ref = repo
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.name().map(ToOwned::to_owned));
+    Ok(worktree::LinkedWorktreeDetails {
+        path: main_repo.to_path_buf(),
+        name: repo_dir_name(main_repo),
+        branch_ref,
+        detached,
+        locked: false,
+        prunable: false,
+    })
+}
+
+/// Window name in the style of `project preset`: `repo` or `repo(worktree)`.
+fn switch_window_name(main_repo: &Path, selected: &CleanupCandidate) -> String {
+    let repo_name = repo_dir_name(main_repo);
+    if selected.path == main_repo {
+        repo_name
+    } else {
+        format!("{}({})", repo_name, selected.name)
+    }
+}
+
+fn repo_dir_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+pub fn cleanup(args: &argparse::WorktreeCleanup) -> anyhow::Result<()> {
     let cwd = std::env::current_dir().context("failed to read current directory")?;
     let repo = git2::Repository::discover(&cwd).context("git")?;
     let repo_workdir = repo
@@ -244,7 +662,7 @@ pub fn cleanup(_args: &argparse::WorktreeCleanup) -> anyhow::Result<()> {
     let main_repo = worktree::resolve_main_repo_path(repo_workdir)
         .context("failed to resolve main repository path")?;
 
-    let candidates = build_cleanup_candidates(&main_repo, repo_workdir)?;
+    let candidates = build_cleanup_candidates(&main_repo, repo_workdir, true)?;
     if candidates.is_empty() {
         anyhow::bail!("no linked worktrees available to clean up");
     }
@@ -254,13 +672,67 @@ pub fn cleanup(_args: &argparse::WorktreeCleanup) -> anyhow::Result<()> {
         anyhow::bail!("no worktrees selected for cleanup");
     }
 
+    let options = CleanupOptions {
+        config: args.config.as_deref(),
+        force: args.force,
+        prompt_unsafe: true,
+        delete_branch: branch_deletion_flags(args.delete_branch, args.delete_remote),
+    };
+    remove_selected_worktrees(selected, &options)
+}
+
+struct CleanupOptions<'a> {
+    config: Option<&'a Path>,
+    force: bool,
+    /// Ask before removing worktrees with unsaved work; otherwise they are kept
+    prompt_unsafe: bool,
+    /// Deletion requested by flags; the config setting applies when it asks for more
+    delete_branch: config::BranchDeletion,
+}
+
+fn branch_deletion_flags(delete_branch: bool, delete_remote: bool) -> config::BranchDeletion {
+    if delete_remote {
+        config::BranchDeletion::Remote
+    } else if delete_branch {
+        config::BranchDeletion::Local
+    } else {
+        config::BranchDeletion::Keep
+    }
+}
+
+fn remove_selected_worktrees(
+    selected: Vec<CleanupCandidate>,
+    options: &CleanupOptions,
+) -> anyhow::Result<()> {
+    let removals = confirm_unsafe_removals(selected, options.force, options.prompt_unsafe)?;
+    if removals.is_empty() {
+        anyhow::bail!("no worktrees left to clean up");
+    }
+
+    let cfg = load_cleanup_config(options.config);
+    let delete_branch = options.delete_branch.max(cfg.worktrees.delete_branch);
+    let protect =
+        regex::RegexSet::new(&cfg.branches.protect).context("invalid branch protection pattern")?;
     let mut failures = Vec::new();
-    for selected_worktree in selected {
-        if let Err(err) = run_git_worktree_remove(&main_repo, &selected_worktree.path) {
+    let mut freed = worktree::DiskUsage::default();
+    for (selected_worktree, force) in removals {
+        if let Err(err) =
+            remove_candidate(&selected_worktree, &cfg.worktrees.hooks.pre_remove, force)
+        {
             failures.push(format!("{}: {}", selected_worktree.path.display(), err));
             continue;
         }
         println!("{}", selected_worktree.path.display());
+        freed += selected_worktree.size.unwrap_or_default();
+
+        if delete_branch != config::BranchDeletion::Keep {
+            if let Err(err) = delete_candidate_branch(&selected_worktree, delete_branch, &protect) {
+                failures.push(format!("{}: {:#}", selected_worktree.path.display(), err));
+            }
+        }
+    }
+    if freed.on_disk > 0 {
+        eprintln!("freed {} on disk", format_size(freed.on_disk));
     }
 
     if !failures.is_empty() {
@@ -273,12 +745,253 @@ pub fn cleanup(_args: &argparse::WorktreeCleanup) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn ensure_destination_parent_exists(destination: &Path) -> anyhow::Result<()> {
-    let parent = destination
-        .parent()
-        .context("worktree destination has no parent directory")?;
-    std::fs::create_dir_all(parent)
-        .with_context(|| format!("failed to create destination parent `{:?}`", parent))
+/// Pair each selected worktree with whether it has to be force-removed.
+///
+/// Worktrees with unsaved work are only removed with `--force` or after the user confirms
+/// the list of what would be lost; otherwise they are kept and only the safe ones go.
+/// Without `ask` they are kept straight away, so unattended runs never lose work.
+fn confirm_unsafe_removals(
+    selected: Vec<CleanupCandidate>,
+    force: bool,
+    ask: bool,
+) -> anyhow::Result<Vec<(CleanupCandidate, bool)>> {
+    let (safe, unsafe_): (Vec<_>, Vec<_>) = selected
+        .into_iter()
+        .partition(|candidate| candidate.unsaved.is_empty());
+    let mut removals = safe
+        .into_iter()
+        .map(|candidate| (candidate, false))
+        .collect::<Vec<_>>();
+    if unsafe_.is_empty() {
+        return Ok(removals);
+    }
+
+    if force {
+        for candidate in &unsafe_ {
+            log::warn!(
+                "force removing `{}` with {}",
+                candidate.path.display(),
+                candidate.unsaved.describe()
+            );
+        }
+    } else {
+        eprintln!("these worktrees have work that exists nowhere else:");
+        for candidate in &unsafe_ {
+            eprintln!(
+                "  {}: {}",
+                candidate.path.display(),
+                candidate.unsaved.describe()
+            );
+        }
+        if !ask
+            || !prompt::confirm(&format!(
+                "remove {} worktrees and lose this work?",
+                unsafe_.len()
+            ))?
+        {
+            log::warn!("keeping {} worktrees with unsaved work", unsafe_.len());
+            return Ok(removals);
+        }
+    }
+
+    removals.extend(unsafe_.into_iter().map(|candidate| (candidate, true)));
+    Ok(removals)
+}
+
+fn load_cleanup_config(config_override: Option<&Path>) -> config::ShelfConfig {
+    match config::load_config(config_override) {
+        Ok(cfg) => cfg,
+        Err(err) => {
+            log::debug!("not using hooks or branch settings from config: {:#}", err);
+            config::ShelfConfig::default()
+        }
+    }
+}
+
+/// Run the pre-remove hooks for a worktree, then remove it.
+///
+/// A failing `abort` hook keeps the worktree; prunable worktrees have no directory to run in.
+fn remove_candidate(
+    candidate: &CleanupCandidate,
+    pre_remove: &[config::WorktreeHook],
+    force: bool,
+) -> anyhow::Result<()> {
+    if candidate.path.is_dir() {
+        let env = hooks::env_for_worktree(
+            &candidate.main_repo,
+            &candidate.path,
+            candidate.branch.as_deref(),
+        );
+        hooks::run_hooks("pre-remove", pre_remove, &env)?;
+    }
+    worktree::remove_worktree(&candidate.main_repo, &candidate.path, force)
+}
+
+/// Delete the branch of a removed worktree when it is merged or its upstream is gone.
+///
+/// Protected branches, the primary branch and branches checked out elsewhere are kept.
+/// Deletions are reported on stderr since stdout carries the removed paths.
+fn delete_candidate_branch(
+    candidate: &CleanupCandidate,
+    deletion: config::BranchDeletion,
+    protect: &regex::RegexSet,
+) -> anyhow::Result<()> {
+    let Some(branch_name) = candidate.branch.as_deref() else {
+        return Ok(());
+    };
+    let repo = git2::Repository::open(&candidate.main_repo).context("git")?;
+    if protect.is_match(branch_name)
+        || git::checked_out_branches(&repo).contains(branch_name)
+        || gitjump::primary_branch_name(&repo).as_deref() == Some(branch_name)
+    {
+        log::info!("keeping protected branch `{}`", branch_name);
+        return Ok(());
+    }
+
+    let Some(reason) = branch::prune_reason(&repo, gitjump::primary_commit(&repo), branch_name)?
+    else {
+        log::warn!(
+            "keeping branch `{}`, it is not merged and its upstream still exists",
+            branch_name
+        );
+        return Ok(());
+    };
+
+    let upstream = if deletion == config::BranchDeletion::Remote {
+        git::local_branch_upstream(&repo, branch_name)
+    } else {
+        None
+    };
+    git::delete_local_branch(&repo, branch_name)?;
+    eprintln!("deleted branch `{}` ({})", branch_name, reason);
+
+    if let Some(upstream) = upstream {
+        let (remote, remote_branch) = git::split_remote_branch(&repo, &upstream)?;
+        git::delete_remote_branch(&candidate.main_repo, &remote, &remote_branch)?;
+        eprintln!("deleted branch `{}` on remote `{}`", remote_branch, remote);
+    }
+    Ok(())
+}
+
+/// Rewrite the worktree name and, unless `--branch` was given, the branch from the
+/// `worktrees.name_template` and `worktrees.branch_template` config.
+fn apply_naming_templates(
+    plan: &mut CreatePlan,
+    cfg: &config::WorktreeConfig,
+    repo: &git2::Repository,
+    default_branch: bool,
+) -> anyhow::Result<()> {
+    let templates = [&cfg.name_template, &cfg.branch_template];
+    if templates.iter().all(|template| template.is_none()) {
+        return Ok(());
+    }
+    let needs_user = templates
+        .iter()
+        .flat_map(|template| template.as_deref())
+        .any(|template| template.contains("{{user}}"));
+    let placeholders = [
+        (
+            "{{user}}",
+            if needs_user {
+                template_user(repo)?
+            } else {
+                String::new()
+            },
+        ),
+        (
+            "{{date}}",
+            chrono::Local::now().format("%Y-%m-%d").to_string(),
+        ),
+        ("{{name}}", plan.name.clone()),
+    ];
+    let render = |template: &str| {
+        placeholders
+            .iter()
+            .fold(template.to_string(), |text, (placeholder, value)| {
+                text.replace(placeholder, value)
+            })
+    };
+
+    if let Some(template) = &cfg.name_template {
+        plan.name = render(template);
+    }
+    if let (Some(template), Some(branch)) = (&cfg.branch_template, &mut plan.branch) {
+        if default_branch {
+            *branch = render(template);
+        }
+    }
+    Ok(())
+}
+
+/// `$USER`, falling back to git's `user.name` with spaces turned into dashes.
+fn template_user(repo: &git2::Repository) -> anyhow::Result<String> {
+    if let Some(user) = std::env::var("USER").ok().filter(|user| !user.is_empty()) {
+        return Ok(user);
+    }
+    let name =
+        gitjump::configured_author(repo)?.context("`{{user}}` needs $USER or git `user.name`")?;
+    Ok(name
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-"))
+}
+
+fn validate_worktree_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+        anyhow::bail!("invalid worktree name `{}`", name);
+    }
+    Ok(())
+}
+
+fn validate_branch_name(branch: &str) -> anyhow::Result<()> {
+    if !git2::Reference::is_valid_name(&format!("refs/heads/{}", branch)) {
+        anyhow::bail!("invalid branch name `{}`", branch);
+    }
+    Ok(())
+}
+
+/// Last component of a branch made safe for a directory: `alex/feature-x` becomes `feature-x`.
+fn worktree_name_from_branch(branch: &str) -> String {
+    let last = branch.rsplit('/').next().unwrap_or(branch);
+    let name = last
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+    let name = name.trim_matches(|c| c == '-' || c == '.');
+    if name.is_empty() {
+        "worktree".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `name`, or `name-2`, `name-3`, ... when a worktree directory of that name exists already.
+fn unique_worktree_name(
+    main_repo: &Path,
+    name: &str,
+    worktree_root: &Path,
+) -> anyhow::Result<String> {
+    for n in 1.. {
+        let candidate = if n == 1 {
+            name.to_string()
+        } else {
+            format!("{}-{}", name, n)
+        };
+        let destination =
+            worktree::build_worktree_destination(main_repo, &candidate, worktree_root)
+                .context("failed to build worktree destination")?;
+        if !destination.exists() {
+            return Ok(candidate);
+        }
+    }
+    unreachable!("unbounded worktree name suffixes")
 }
 
 fn ensure_destination_missing(destination: &Path) -> anyhow::Result<()> {
@@ -291,50 +1004,154 @@ fn ensure_destination_missing(destination: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn symlink_setup_paths(main_repo: &Path, destination: &Path, setup: &[String]) {
-    for relative in setup {
-        let source = main_repo.join(relative);
-        if !source.exists() {
-            continue;
-        }
-        let target = destination.join(relative);
-        if target.exists() || target.symlink_metadata().is_ok() {
-            continue;
-        }
-        if let Some(parent) = target.parent() {
-            if let Err(err) = std::fs::create_dir_all(parent) {
-                log::warn!(
-                    "worktree setup: failed to create parent dirs for `{}`: {}",
-                    target.display(),
-                    err
-                );
-                continue;
-            }
-        }
-        if let Err(err) = std::os::unix::fs::symlink(&source, &target) {
-            log::warn!(
-                "worktree setup: failed to symlink `{}` -> `{}`: {}",
-                target.display(),
-                source.display(),
-                err
-            );
+/// libgit2 cannot create detached or sparse worktrees, so those still go through
+/// `git worktree add`.
+fn add_worktree_steps(
+    main_repo: &Path,
+    destination: &Path,
+    plan: &CreatePlan,
+    sparse_dirs: Option<&[String]>,
+    transaction: &mut CreateTransaction,
+) -> anyhow::Result<()> {
+    let parent = destination
+        .parent()
+        .context("worktree destination has no parent directory")?;
+    // unlike `git worktree add`, libgit2 does not create missing parents
+    transaction.create_dir_all(parent)?;
+    let added = Step::AddedWorktree {
+        main_repo: main_repo.to_path_buf(),
+        path: destination.to_path_buf(),
+    };
+
+    let Some(branch_name) = plan.branch.as_deref() else {
+        run_git_worktree_add(main_repo, destination, plan, sparse_dirs.is_some())?;
+        transaction.record(added);
+        if let Some(dirs) = sparse_dirs {
+            sparse::checkout_sparse(destination, dirs)?;
         }
+        return Ok(());
+    };
+    let repo = git2::Repository::open(main_repo)
+        .with_context(|| format!("failed to open repository `{}`", main_repo.display()))?;
+    let mut branch = if plan.existing_branch {
+        repo.find_branch(branch_name, git2::BranchType::Local)
+            .with_context(|| format!("could not find local branch `{}`", branch_name))?
+    } else {
+        let branch = create_branch(&repo, branch_name, plan.commitish.as_deref())?;
+        transaction.record(Step::CreatedBranch {
+            main_repo: main_repo.to_path_buf(),
+            name: branch_name.to_string(),
+        });
+        branch
+    };
+
+    if plan.track {
+        let upstream = plan
+            .commitish
+            .as_deref()
+            .and_then(|commitish| commitish.strip_prefix("refs/remotes/"))
+            .context("only remote-tracking branches can be tracked")?;
+        branch
+            .set_upstream(Some(upstream))
+            .with_context(|| format!("failed to track `{}`", upstream))?;
     }
+
+    if let Some(dirs) = sparse_dirs {
+        // the branch exists now, so git only has to add it without checking anything out
+        let existing = CreatePlan {
+            existing_branch: true,
+            ..plan.clone()
+        };
+        run_git_worktree_add(main_repo, destination, &existing, true)?;
+        transaction.record(added);
+        return sparse::checkout_sparse(destination, dirs);
+    }
+
+    let admin_name = unique_worktree_admin_name(&repo, destination);
+    let mut options = git2::WorktreeAddOptions::new();
+    options.reference(Some(branch.get()));
+    if let Err(err) = repo.worktree(&admin_name, destination, Some(&options)) {
+        // libgit2 leaves a partial checkout and admin dir behind; the destination was
+        // checked to be missing, so anything there is ours
+        let _ = std::fs::remove_dir_all(destination);
+        let _ = std::fs::remove_dir_all(repo.path().join("worktrees").join(&admin_name));
+        return Err(err)
+            .with_context(|| format!("failed to add worktree `{}`", destination.display()));
+    }
+    transaction.record(added);
+    Ok(())
 }
 
-fn build_worktree_add_args(args: &argparse::WorktreeCreate, destination: &Path) -> Vec<OsString> {
+fn create_branch<'r>(
+    repo: &'r git2::Repository,
+    branch_name: &str,
+    commitish: Option<&str>,
+) -> anyhow::Result<git2::Branch<'r>> {
+    let start = match commitish {
+        Some(commitish) => repo
+            .revparse_single(commitish)
+            .and_then(|object| object.peel_to_commit())
+            .with_context(|| format!("failed to resolve `{}`", commitish))?,
+        None => repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .context("failed to resolve HEAD")?,
+    };
+    if repo
+        .find_branch(branch_name, git2::BranchType::Local)
+        .is_ok()
+    {
+        anyhow::bail!("a branch named `{}` already exists", branch_name);
+    }
+    repo.branch(branch_name, &start, false)
+        .with_context(|| format!("failed to create branch `{}`", branch_name))
+}
+
+/// Name of the admin dir under `.git/worktrees`, suffixed like git does when taken.
+fn unique_worktree_admin_name(repo: &git2::Repository, destination: &Path) -> String {
+    let base = destination
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "worktree".to_string());
+    let admin_dir = repo.path().join("worktrees");
+    if !admin_dir.join(&base).exists() {
+        return base;
+    }
+    (1..)
+        .map(|n| format!("{}{}", base, n))
+        .find(|name| !admin_dir.join(name).exists())
+        .expect("unbounded suffixes")
+}
+
+fn build_worktree_add_args(
+    plan: &CreatePlan,
+    destination: &Path,
+    no_checkout: bool,
+) -> Vec<OsString> {
     let mut git_args: Vec<OsString> = vec!["worktree".into(), "add".into()];
+    if no_checkout {
+        git_args.push("--no-checkout".into());
+    }
 
-    if args.detach {
-        git_args.push("--detach".into());
-    } else {
-        git_args.push("-b".into());
-        git_args.push(args.branch.as_deref().unwrap_or(&args.name).into());
+    match &plan.branch {
+        Some(branch) if plan.existing_branch => {
+            git_args.push(destination.as_os_str().to_os_string());
+            git_args.push(branch.into());
+            return git_args;
+        }
+        Some(branch) => {
+            if plan.track {
+                git_args.push("--track".into());
+            }
+            git_args.push("-b".into());
+            git_args.push(branch.into());
+        }
+        None => git_args.push("--detach".into()),
     }
 
     git_args.push(destination.as_os_str().to_os_string());
 
-    if let Some(commitish) = &args.commitish {
+    if let Some(commitish) = &plan.commitish {
         git_args.push(commitish.into());
     }
 
@@ -344,9 +1161,10 @@ fn build_worktree_add_args(args: &argparse::WorktreeCreate, destination: &Path)
 fn run_git_worktree_add(
     main_repo: &Path,
     destination: &Path,
-    args: &argparse::WorktreeCreate,
+    plan: &CreatePlan,
+    no_checkout: bool,
 ) -> anyhow::Result<()> {
-    let git_args = build_worktree_add_args(args, destination);
+    let git_args = build_worktree_add_args(plan, destination, no_checkout);
     let status = Command::new("git")
         .arg("-C")
         .arg(main_repo)
@@ -374,22 +1192,42 @@ fn run_git_worktree_add(
 fn build_cleanup_candidates(
     main_repo: &Path,
     current_workdir: &Path,
+    measure_size: bool,
 ) -> anyhow::Result<Vec<Arc<CleanupCandidate>>> {
-    let linked_worktrees = worktree::list_linked_worktree_details(main_repo)?;
-    let mut candidates = Vec::new();
-
-    for details in linked_worktrees {
-        if details.path == current_workdir {
-            continue;
-        }
-        candidates.push(Arc::new(CleanupCandidate::from_details(details, main_repo)));
-    }
+    let linked_worktrees = worktree::list_linked_worktree_details(main_repo)?
+        .into_iter()
+        .filter(|details| details.path != current_workdir)
+        .collect::<Vec<_>>();
+    let sizes = if measure_size {
+        let paths = linked_worktrees
+            .iter()
+            .map(|details| details.path.clone())
+            .collect::<Vec<_>>();
+        du::measure_worktrees(&paths)
+            .into_iter()
+            .map(Some)
+            .collect()
+    } else {
+        vec![None; linked_worktrees.len()]
+    };
 
-    Ok(candidates)
+    Ok(linked_worktrees
+        .into_iter()
+        .zip(sizes)
+        .map(|(details, size)| {
+            Arc::new(CleanupCandidate::from_details_with_repo(
+                details, None, main_repo, size,
+            ))
+        })
+        .collect())
 }
 
 fn select_worktrees_to_cleanup(candidates: Vec<Arc<CleanupCandidate>>) -> Vec<CleanupCandidate> {
-    let options = SkimOptionsBuilder::default().multi(true).build().unwrap();
+    select_worktrees(candidates, true)
+}
+
+fn select_worktrees(candidates: Vec<Arc<CleanupCandidate>>, multi: bool) -> Vec<CleanupCandidate> {
+    let options = SkimOptionsBuilder::default().multi(multi).build().unwrap();
 
     let (send, recv): (SkimItemSender, SkimItemReceiver) = skim::prelude::unbounded();
     for candidate in candidates {
@@ -414,40 +1252,42 @@ fn select_worktrees_to_cleanup(candidates: Vec<Arc<CleanupCandidate>>) -> Vec<Cl
         .collect()
 }
 
-fn build_worktree_remove_args(destination: &Path) -> Vec<OsString> {
-    vec![
-        "worktree".into(),
-        "remove".into(),
-        "--force".into(),
-        destination.as_os_str().to_os_string(),
-    ]
-}
-
-fn run_git_worktree_remove(main_repo: &Path, destination: &Path) -> anyhow::Result<()> {
-    let git_args = build_worktree_remove_args(destination);
-    let status = Command::new("git")
-        .arg("-C")
-        .arg(main_repo)
-        .args(&git_args)
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::inherit())
-        .status()
-        .with_context(|| {
-            format!(
-                "failed to execute `git worktree remove --force` for repository `{}`",
-                main_repo.display()
-            )
-        })?;
+/// Left-aligned columns separated by two spaces, one line per row.
+fn render_columns(rows: &[Vec<String>]) -> String {
+    let mut widths = Vec::new();
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.chars().count());
+        }
+    }
 
-    if !status.success() {
-        anyhow::bail!(
-            "`git worktree remove --force` failed for destination `{}`",
-            destination.display()
-        );
+    let mut out = String::new();
+    for row in rows {
+        let line = row
+            .iter()
+            .zip(&widths)
+            .map(|(column, width)| format!("{:width$}", column, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        out.push_str(line.trim_end());
+        out.push('\n');
     }
+    out
+}
 
-    Ok(())
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= KIB && unit < SIZE_UNITS.len() - 1 {
+        size /= KIB;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, SIZE_UNITS[0])
+    } else {
+        format!("{:.1} {}", size, SIZE_UNITS[unit])
+    }
 }
 
 fn find_upstream_branch(path: &Path, local_branch: Option<&str>) -> Option<String> {
@@ -466,38 +1306,24 @@ fn find_upstream_branch(path: &Path, local_branch: Option<&str>) -> Option<Strin
     )
 }
 
-fn is_worktree_dirty(path: &Path) -> bool {
-    let repo = match git2::Repository::open(path) {
-        Ok(repo) => repo,
+fn find_unsaved_work(path: &Path) -> git::UnsavedWork {
+    match git::inspect_unsaved_work(path) {
+        Ok(unsaved) => unsaved,
         Err(err) => {
-            log::debug!("failed to open worktree `{:?}`: {}", path, err);
-            return false;
-        }
-    };
-
-    let mut status_opts = git2::StatusOptions::new();
-    status_opts
-        .include_untracked(true)
-        .recurse_untracked_dirs(true)
-        .renames_head_to_index(true)
-        .renames_index_to_workdir(true)
-        .include_ignored(false);
-
-    let statuses = repo.statuses(Some(&mut status_opts));
-    match statuses {
-        Ok(statuses) => !statuses.is_empty(),
-        Err(err) => {
-            log::debug!("failed to inspect worktree status `{:?}`: {}", path, err);
-            false
+            log::debug!("failed to inspect worktree `{:?}`: {:#}", path, err);
+            git::UnsavedWork::default()
         }
     }
 }
 
-fn find_head_commit_message(path: &Path) -> Option<String> {
+fn find_head_commit(path: &Path) -> Option<git::GitCommit> {
     let repo = git2::Repository::open(path).ok()?;
     let head = repo.head().ok()?;
     let commit = head.peel_to_commit().ok()?;
-    let message = String::from_utf8_lossy(commit.message_bytes());
+    Some(git::GitCommit::from_commit(&commit))
+}
+
+fn commit_summary(message: &str) -> Option<String> {
     let first_line = message.lines().next().map(str::trim).unwrap_or("");
     if first_line.is_empty() {
         return None;
@@ -506,12 +1332,72 @@ fn find_head_commit_message(path: &Path) -> Option<String> {
 }
 
 #[cfg(test)]
-mod tests {
+mod test_util {
     use std::path::Path;
 
-    use super::{build_worktree_add_args, build_worktree_remove_args};
+    use super::{add_worktree_steps, CreatePlan, CreateTransaction};
     use crate::argparse::WorktreeCreate;
 
+    /// `worktree create` arguments with every option left out.
+    pub(super) fn create_args() -> WorktreeCreate {
+        WorktreeCreate {
+            name: None,
+            branch: None,
+            detach: false,
+            commitish: None,
+            from_remote: None,
+            fetch_ref: None,
+            remote: None,
+            sparse: None,
+            config: None,
+        }
+    }
+
+    /// Plan for worktree `name` on a new branch of the same name, created from HEAD.
+    pub(super) fn branch_plan(name: &str) -> CreatePlan {
+        let args = WorktreeCreate {
+            name: Some(name.to_string()),
+            ..create_args()
+        };
+        CreatePlan::from_args(&args, name.to_string())
+    }
+
+    /// Create the worktree and its branch through git2, undoing both if it fails.
+    pub(super) fn add_worktree(
+        main_repo: &Path,
+        destination: &Path,
+        plan: &CreatePlan,
+    ) -> anyhow::Result<()> {
+        let mut transaction = CreateTransaction::default();
+        add_worktree_steps(main_repo, destination, plan, None, &mut transaction)
+            .map_err(|err| transaction.rollback(err))
+    }
+
+    /// Add a worktree at `destination` on a new branch named after its directory.
+    pub(super) fn add_branch_worktree(main_repo: &Path, destination: &Path) {
+        let name = destination.file_name().unwrap().to_string_lossy();
+        add_worktree(main_repo, destination, &branch_plan(&name)).expect("add worktree");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::test_util::{add_worktree, create_args};
+    use super::{
+        apply_naming_templates, build_worktree_add_args, create_steps, delete_candidate_branch,
+        format_size, main_checkout_details, switch_window_name, unique_worktree_name,
+        validate_branch_name, worktree_name_from_branch, CleanupCandidate, CleanupFilter,
+        CreatePlan,
+    };
+    use crate::{
+        argparse::WorktreeCreate,
+        config::BranchDeletion,
+        git::{self, test_util},
+        worktree,
+    };
+
     fn mk_args(
         name: &str,
         branch: Option<&str>,
@@ -519,14 +1405,23 @@ mod tests {
         commitish: Option<&str>,
     ) -> WorktreeCreate {
         WorktreeCreate {
-            name: name.to_string(),
+            name: Some(name.to_string()),
             branch: branch.map(ToString::to_string),
             detach,
             commitish: commitish.map(ToString::to_string),
-            config: None,
+            ..create_args()
         }
     }
 
+    fn mk_plan(
+        name: &str,
+        branch: Option<&str>,
+        detach: bool,
+        commitish: Option<&str>,
+    ) -> CreatePlan {
+        CreatePlan::from_args(&mk_args(name, branch, detach, commitish), name.to_string())
+    }
+
     fn to_strings(args: Vec<std::ffi::OsString>) -> Vec<String> {
         args.into_iter()
             .map(|s| s.to_string_lossy().to_string())
@@ -535,16 +1430,16 @@ mod tests {
 
     #[test]
     fn defaults_to_branch_named_after_worktree() {
-        let args = mk_args("handle-foo", None, false, None);
-        let got = to_strings(build_worktree_add_args(&args, Path::new("/tmp/wt")));
+        let plan = mk_plan("handle-foo", None, false, None);
+        let got = to_strings(build_worktree_add_args(&plan, Path::new("/tmp/wt"), false));
 
         assert_eq!(got, vec!["worktree", "add", "-b", "handle-foo", "/tmp/wt"]);
     }
 
     #[test]
     fn supports_distinct_branch_name() {
-        let args = mk_args("handle-foo", Some("alice/feature-x"), false, None);
-        let got = to_strings(build_worktree_add_args(&args, Path::new("/tmp/wt")));
+        let plan = mk_plan("handle-foo", Some("alice/feature-x"), false, None);
+        let got = to_strings(build_worktree_add_args(&plan, Path::new("/tmp/wt"), false));
 
         assert_eq!(
             got,
@@ -554,8 +1449,8 @@ mod tests {
 
     #[test]
     fn supports_detached_creation_with_commitish() {
-        let args = mk_args("handle-foo", None, true, Some("origin/main"));
-        let got = to_strings(build_worktree_add_args(&args, Path::new("/tmp/wt")));
+        let plan = mk_plan("handle-foo", None, true, Some("origin/main"));
+        let got = to_strings(build_worktree_add_args(&plan, Path::new("/tmp/wt"), false));
 
         assert_eq!(
             got,
@@ -565,8 +1460,8 @@ mod tests {
 
     #[test]
     fn supports_branch_creation_from_commitish() {
-        let args = mk_args("handle-foo", None, false, Some("origin/main"));
-        let got = to_strings(build_worktree_add_args(&args, Path::new("/tmp/wt")));
+        let plan = mk_plan("handle-foo", None, false, Some("origin/main"));
+        let got = to_strings(build_worktree_add_args(&plan, Path::new("/tmp/wt"), false));
 
         assert_eq!(
             got,
@@ -582,9 +1477,294 @@ mod tests {
     }
 
     #[test]
-    fn cleanup_removes_worktree_with_force() {
-        let got = to_strings(build_worktree_remove_args(Path::new("/tmp/wt")));
+    fn adds_worktrees_on_new_branches_through_git2() {
+        let root = test_util::unique_temp_path("add-worktree");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+
+        let plan = mk_plan("feature", None, false, None);
+        add_worktree(&main_repo, &root.join("feature"), &plan).expect("add worktree");
+        assert!(root.join("feature").join("README.md").exists());
+
+        let err = add_worktree(&main_repo, &root.join("again"), &plan).expect_err("branch exists");
+        assert!(err.to_string().contains("already exists"));
+        assert!(!root.join("again").exists());
+
+        let bad = mk_plan("bad", None, false, Some("no-such-rev"));
+        assert!(add_worktree(&main_repo, &root.join("bad"), &bad).is_err());
+        assert!(repo.find_branch("bad", git2::BranchType::Local).is_err());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    #[test]
+    fn cleanup_deletes_only_merged_worktree_branches() {
+        let root = test_util::unique_temp_path("cleanup-branches");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+        let head = repo.head().unwrap().peel_to_commit().unwrap().id();
+        repo.reference("refs/remotes/origin/main", head, false, "test")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            false,
+            "test",
+        )
+        .unwrap();
+
+        for name in ["merged-wt", "unmerged-wt"] {
+            let plan = mk_plan(name, None, false, None);
+            add_worktree(&main_repo, &root.join(name), &plan).expect("add worktree");
+        }
+        let unmerged = git2::Repository::open(root.join("unmerged-wt")).unwrap();
+        test_util::commit_file(&unmerged, "wip.txt", "wip\n", "wip");
+
+        let protect = regex::RegexSet::empty();
+        for details in worktree::list_linked_worktree_details(&main_repo).expect("list") {
+            let candidate = CleanupCandidate::from_details(details, &main_repo);
+            worktree::remove_worktree(&main_repo, &candidate.path, true).expect("remove");
+            delete_candidate_branch(&candidate, BranchDeletion::Local, &protect)
+                .expect("delete branch");
+        }
+
+        assert!(repo
+            .find_branch("merged-wt", git2::BranchType::Local)
+            .is_err());
+        assert!(repo
+            .find_branch("unmerged-wt", git2::BranchType::Local)
+            .is_ok());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    fn candidate(main_repo: &str, commit_time: i64) -> CleanupCandidate {
+        CleanupCandidate {
+            path: Path::new(main_repo).join("wt"),
+            main_repo: main_repo.into(),
+            name: "wt".to_string(),
+            branch: None,
+            upstream: None,
+            commit_message: None,
+            commit_time: Some(commit_time),
+            dirty: false,
+            unsaved: Default::default(),
+            detached: true,
+            locked: false,
+            prunable: false,
+            size: None,
+            display_str: skim::AnsiString::parse("wt"),
+        }
+    }
+
+    #[test]
+    fn cleanup_filters_must_all_match() {
+        let now = 100 * 86_400;
+        let mut filter = CleanupFilter {
+            merged: false,
+            older_than: None,
+            prunable: false,
+            clean_only: false,
+            repo: None,
+            now,
+        };
+        assert!(!filter.is_active());
+
+        filter.older_than = Some(chrono::Duration::days(30));
+        filter.repo = Some(regex::Regex::new("/src/api$").unwrap());
+        assert!(filter.is_active());
+
+        let old = candidate("/src/api", now - 31 * 86_400);
+        let recent = candidate("/src/api", now - 86_400);
+        let other_repo = candidate("/src/web", now - 31 * 86_400);
+        assert!(filter.matches(&old));
+        assert!(!filter.matches(&recent));
+        assert!(!filter.matches(&other_repo));
+
+        let mut dirty = old.clone();
+        dirty.unsaved.modified = 1;
+        filter.clean_only = true;
+        assert!(filter.matches(&old));
+        assert!(!filter.matches(&dirty));
+
+        filter.prunable = true;
+        assert!(!filter.matches(&old));
+
+        filter.prunable = false;
+        filter.merged = true;
+        assert!(!filter.matches(&old), "detached worktrees are never merged");
+    }
+
+    #[test]
+    fn switch_lists_main_checkout_and_names_windows() {
+        let root = test_util::unique_temp_path("switch");
+        let main_repo = root.join("api");
+        test_util::init_repo(&main_repo);
+
+        let details = main_checkout_details(&main_repo).expect("main details");
+        assert_eq!(details.name, "api");
+        assert_eq!(details.branch_ref.as_deref(), Some("refs/heads/main"));
+        assert!(!details.detached);
+
+        let main = CleanupCandidate::from_details(details, &main_repo);
+        assert_eq!(switch_window_name(&main_repo, &main), "api");
+        let linked = candidate(main_repo.to_str().unwrap(), 0);
+        assert_eq!(switch_window_name(&main_repo, &linked), "api(wt)");
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    #[test]
+    fn sizes_are_human_readable() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn names_worktrees_after_the_last_branch_component() {
+        assert_eq!(worktree_name_from_branch("alex/feature-x"), "feature-x");
+        assert_eq!(worktree_name_from_branch("fix#12"), "fix-12");
+        assert_eq!(worktree_name_from_branch("release/.hidden"), "hidden");
+        assert_eq!(worktree_name_from_branch("wip/"), "worktree");
+    }
 
-        assert_eq!(got, vec!["worktree", "remove", "--force", "/tmp/wt"]);
+    #[test]
+    fn picked_branches_are_checked_out_or_tracked() {
+        let root = test_util::unique_temp_path("create-picked");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("alex/feature-x", &head, false).unwrap();
+        repo.reference("refs/remotes/origin/alice/fix", head.id(), false, "test")
+            .unwrap();
+        let picked = |name: &str, ref_name: &str, branch_type| git::GitBranch {
+            name: name.to_string(),
+            ref_name: ref_name.to_string(),
+            branch_type,
+            head: false,
+            upstream: None,
+        };
+        let args = mk_args("unused", None, false, None);
+
+        let local = picked(
+            "alex/feature-x",
+            "refs/heads/alex/feature-x",
+            git2::BranchType::Local,
+        );
+        let plan = CreatePlan::from_picked_branch(&repo, &local, &args).expect("local plan");
+        assert_eq!(plan.name, "feature-x");
+        assert!(plan.existing_branch);
+        add_worktree(&main_repo, &root.join("wt/feature-x"), &plan).expect("add local");
+        assert!(CreatePlan::from_picked_branch(&repo, &local, &args).is_err());
+
+        let remote = picked(
+            "origin/alice/fix",
+            "refs/remotes/origin/alice/fix",
+            git2::BranchType::Remote,
+        );
+        let plan = CreatePlan::from_picked_branch(&repo, &remote, &args).expect("remote plan");
+        assert_eq!(plan.branch.as_deref(), Some("alice/fix"));
+        assert!(plan.track && !plan.existing_branch);
+        add_worktree(&main_repo, &root.join("wt/fix"), &plan).expect("add remote");
+        assert_eq!(
+            git::local_branch_upstream(&repo, "alice/fix").as_deref(),
+            Some("refs/remotes/origin/alice/fix")
+        );
+
+        let worktree_root = root.join("worktrees");
+        let taken =
+            worktree::build_worktree_destination(&main_repo, "fix", &worktree_root).unwrap();
+        std::fs::create_dir_all(taken).unwrap();
+        assert_eq!(
+            unique_worktree_name(&main_repo, "fix", &worktree_root).unwrap(),
+            "fix-2"
+        );
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    #[test]
+    fn naming_templates_shape_default_branches_and_names() {
+        let root = test_util::unique_temp_path("naming-templates");
+        let repo = test_util::init_repo(&root);
+        let cfg = crate::config::WorktreeConfig {
+            branch_template: Some("team/{{name}}".to_string()),
+            name_template: Some("{{date}}-{{name}}".to_string()),
+            ..Default::default()
+        };
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+
+        let mut plan = mk_plan("handle-foo", None, false, None);
+        apply_naming_templates(&mut plan, &cfg, &repo, true).expect("templates");
+        assert_eq!(plan.name, format!("{}-handle-foo", date));
+        assert_eq!(plan.branch.as_deref(), Some("team/handle-foo"));
+
+        let mut plan = mk_plan("handle-foo", Some("alex/custom"), false, None);
+        apply_naming_templates(&mut plan, &cfg, &repo, false).expect("templates");
+        assert_eq!(plan.branch.as_deref(), Some("alex/custom"));
+
+        let mut plan = mk_plan("handle-foo", None, true, None);
+        apply_naming_templates(&mut plan, &cfg, &repo, true).expect("templates");
+        assert_eq!(plan.branch, None);
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    #[test]
+    fn rejects_illegal_branch_names() {
+        assert!(validate_branch_name("alex/handle-foo").is_ok());
+        assert!(validate_branch_name("alex/../foo").is_err());
+        assert!(validate_branch_name("feature..x").is_err());
+        assert!(validate_branch_name("wip ticket").is_err());
+        assert!(validate_branch_name("fix.lock").is_err());
+    }
+
+    #[test]
+    fn failed_create_rolls_back_every_step() {
+        let root = test_util::unique_temp_path("create-transaction");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+        std::fs::write(main_repo.join(".envrc"), "use nix\n").unwrap();
+        let cfg: crate::config::ShelfConfig = serde_yaml::from_str(
+            r###"
+            worktrees:
+              setup:
+                - .envrc
+              hooks:
+                post_create:
+                  - command: exit 3
+                    on_failure: abort
+            "###,
+        )
+        .unwrap();
+        let destination = root.join("worktrees/slug/feature");
+        let plan = mk_plan("feature", None, false, None);
+
+        let mut transaction = super::CreateTransaction::default();
+        let err = create_steps(
+            &main_repo,
+            &destination,
+            &plan,
+            None,
+            Some(&cfg),
+            &mut transaction,
+        )
+        .expect_err("hook fails");
+        let message = format!("{:#}", transaction.rollback(err));
+
+        assert!(message.contains("removed setup entry"));
+        assert!(message.contains("removed worktree"));
+        assert!(message.contains("removed branch `feature`"));
+        assert!(message.contains(&format!(
+            "removed directory `{}`",
+            root.join("worktrees").display()
+        )));
+        assert!(!root.join("worktrees").exists());
+        assert!(main_repo.join(".envrc").exists());
+        assert!(repo
+            .find_branch("feature", git2::BranchType::Local)
+            .is_err());
+        assert!(worktree::list_linked_worktree_details(&main_repo)
+            .expect("list")
+            .is_empty());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
     }
 }
@@ -0,0 +1,556 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use walkdir::WalkDir;
+
+use super::manage::same_path;
+use crate::{argparse, config, git, prompt, scan::scan_git_repos, worktree};
+
+/// Something under the worktree root that git or shelf can no longer follow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Problem {
+    /// Slug dir whose main repository cannot be found
+    OrphanedSlugDir { slug_dir: PathBuf },
+    /// Slug dir whose main repository moved to `main_repo`
+    MovedRepo {
+        slug_dir: PathBuf,
+        main_repo: PathBuf,
+    },
+    /// `.git/worktrees/<name>` entry whose working tree is gone
+    StaleAdminEntry {
+        main_repo: PathBuf,
+        name: String,
+        worktree_path: PathBuf,
+    },
+    /// Working tree and admin entry that no longer point at each other
+    BrokenLink {
+        worktree_path: PathBuf,
+        admin_dir: PathBuf,
+    },
+    /// Working tree whose `.git` file points at an admin entry that does not exist
+    UnlinkedWorktree { worktree_path: PathBuf },
+}
+
+impl Problem {
+    fn fix(&self) -> &'static str {
+        match self {
+            Problem::StaleAdminEntry { .. } => "prune",
+            Problem::MovedRepo { .. } => "repoint",
+            Problem::BrokenLink { .. } => "repair",
+            Problem::OrphanedSlugDir { .. } | Problem::UnlinkedWorktree { .. } => "delete",
+        }
+    }
+
+    fn path(&self) -> &Path {
+        match self {
+            Problem::OrphanedSlugDir { slug_dir } | Problem::MovedRepo { slug_dir, .. } => slug_dir,
+            Problem::StaleAdminEntry { worktree_path, .. }
+            | Problem::BrokenLink { worktree_path, .. }
+            | Problem::UnlinkedWorktree { worktree_path } => worktree_path,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Problem::OrphanedSlugDir { slug_dir } => format!(
+                "`{}` does not belong to an existing repository",
+                slug_dir.display()
+            ),
+            Problem::MovedRepo {
+                slug_dir,
+                main_repo,
+            } => format!(
+                "the repository of `{}` moved to `{}`",
+                slug_dir.display(),
+                main_repo.display()
+            ),
+            Problem::StaleAdminEntry {
+                main_repo, name, ..
+            } => format!(
+                "worktree `{}` of `{}` points at a missing directory",
+                name,
+                main_repo.display()
+            ),
+            Problem::BrokenLink {
+                worktree_path,
+                admin_dir,
+            } => format!(
+                "`{}` and `{}` do not link to each other",
+                worktree_path.display(),
+                admin_dir.display()
+            ),
+            Problem::UnlinkedWorktree { worktree_path } => format!(
+                "`{}` is not registered with its repository",
+                worktree_path.display()
+            ),
+        }
+    }
+
+    fn apply(&self) -> anyhow::Result<()> {
+        match self {
+            Problem::OrphanedSlugDir { slug_dir: path }
+            | Problem::UnlinkedWorktree {
+                worktree_path: path,
+            } => {
+                ensure_nothing_to_lose(path)?;
+                std::fs::remove_dir_all(path)
+                    .with_context(|| format!("failed to delete `{}`", path.display()))
+            }
+            Problem::MovedRepo {
+                slug_dir,
+                main_repo,
+            } => {
+                worktree::replace_slug_metadata(slug_dir, main_repo)?;
+                for problem in find_link_problems(slug_dir, main_repo) {
+                    if let Problem::BrokenLink { .. } = problem {
+                        problem.apply()?;
+                    }
+                }
+                Ok(())
+            }
+            Problem::StaleAdminEntry {
+                main_repo, name, ..
+            } => {
+                let repo = git2::Repository::open(main_repo).context("git")?;
+                repo.find_worktree(name)?
+                    .prune(None)
+                    .with_context(|| format!("failed to prune worktree `{}`", name))
+            }
+            Problem::BrokenLink {
+                worktree_path,
+                admin_dir,
+            } => repair_links(worktree_path, admin_dir),
+        }
+    }
+}
+
+pub fn doctor(args: &argparse::WorktreeDoctor) -> anyhow::Result<()> {
+    let worktree_roots = worktree::resolve_worktree_roots(args.config.as_deref())?;
+    // where to look for repositories that moved away from their slug dirs
+    let groups = config::load_config(args.config.as_deref())?.projects;
+    let mut problems = Vec::new();
+    for worktree_root in &worktree_roots {
+        if !worktree_root.is_dir() {
+            log::warn!("worktree root `{}` does not exist", worktree_root.display());
+            continue;
+        }
+        for problem in find_problems(worktree_root, &groups)? {
+            // a repo with worktrees under several roots reports its stale entries under each
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+    }
+    if problems.is_empty() {
+        let roots = worktree_roots
+            .iter()
+            .map(|root| format!("`{}`", root.display()))
+            .collect::<Vec<_>>();
+        eprintln!("no problems found under {}", roots.join(", "));
+        return Ok(());
+    }
+
+    let mut failures = Vec::new();
+    for problem in &problems {
+        if args.dry_run {
+            println!("{}\t{}", problem.fix(), problem.path().display());
+            continue;
+        }
+        eprintln!("{}", problem.describe());
+        if !args.yes
+            && !prompt::confirm(&format!(
+                "{} `{}`?",
+                problem.fix(),
+                problem.path().display()
+            ))?
+        {
+            continue;
+        }
+        match problem.apply() {
+            Ok(()) => println!("{}\t{}", problem.fix(), problem.path().display()),
+            Err(err) => failures.push(format!("{}: {:#}", problem.path().display(), err)),
+        }
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("failed to fix some problems:\n{}", failures.join("\n"));
+    }
+    Ok(())
+}
+
+fn find_problems(
+    worktree_root: &Path,
+    groups: &[config::ProjectGroup],
+) -> anyhow::Result<Vec<Problem>> {
+    let mut slug_dirs = std::fs::read_dir(worktree_root)
+        .with_context(|| format!("failed to read `{}`", worktree_root.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    slug_dirs.sort();
+
+    let mut problems = Vec::new();
+    let mut main_repos = Vec::new();
+    let mut known_repos = None;
+    for slug_dir in slug_dirs {
+        let main_repo = worktree::resolve_slug_dir_repo(&slug_dir)
+            .filter(|main_repo| main_repo.join(".git").is_dir());
+        let Some(main_repo) = main_repo else {
+            let known_repos = known_repos.get_or_insert_with(|| scan_project_repos(groups));
+            match find_moved_repo(&slug_dir, known_repos) {
+                Some(main_repo) => problems.push(Problem::MovedRepo {
+                    slug_dir,
+                    main_repo,
+                }),
+                None => problems.push(Problem::OrphanedSlugDir { slug_dir }),
+            }
+            continue;
+        };
+        problems.extend(find_link_problems(&slug_dir, &main_repo));
+        if !main_repos.contains(&main_repo) {
+            main_repos.push(main_repo);
+        }
+    }
+
+    for main_repo in main_repos {
+        let repaired = problems
+            .iter()
+            .filter_map(|problem| match problem {
+                Problem::BrokenLink { admin_dir, .. } => Some(admin_dir.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        problems.extend(find_stale_admin_entries(&main_repo, &repaired)?);
+    }
+    Ok(problems)
+}
+
+fn scan_project_repos(groups: &[config::ProjectGroup]) -> Vec<PathBuf> {
+    groups
+        .iter()
+        .flat_map(|group| {
+            let ignore = regex::bytes::RegexSet::new(&group.exclude)
+                .unwrap_or_else(|_| regex::bytes::RegexSet::empty());
+            scan_git_repos(&group.root, ignore)
+        })
+        .collect()
+}
+
+/// The repository whose admin entries link back to a worktree in the slug dir.
+fn find_moved_repo(slug_dir: &Path, known_repos: &[PathBuf]) -> Option<PathBuf> {
+    let worktrees = linked_children(slug_dir);
+    known_repos
+        .iter()
+        .find(|repo| {
+            let admin_dirs = std::fs::read_dir(repo.join(".git").join("worktrees"))
+                .into_iter()
+                .flatten()
+                .flatten()
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>();
+            worktrees.iter().any(|worktree_path| {
+                admin_dirs
+                    .iter()
+                    .any(|admin_dir| links_back(admin_dir, worktree_path))
+            })
+        })
+        .cloned()
+}
+
+/// Directories in a slug dir that have a `.git` file, sorted.
+fn linked_children(slug_dir: &Path) -> Vec<PathBuf> {
+    let mut children = std::fs::read_dir(slug_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.join(".git").is_file())
+        .collect::<Vec<_>>();
+    children.sort();
+    children
+}
+
+/// Check that every worktree in a slug dir and its admin entry point at each other.
+fn find_link_problems(slug_dir: &Path, main_repo: &Path) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for worktree_path in linked_children(slug_dir) {
+        let admin_dir = match worktree::worktree_admin_dir(&worktree_path) {
+            Ok(admin_dir) => admin_dir,
+            Err(err) => {
+                log::warn!("{:#}", err);
+                continue;
+            }
+        };
+        if admin_dir.is_dir() {
+            if !links_back(&admin_dir, &worktree_path) {
+                problems.push(Problem::BrokenLink {
+                    worktree_path,
+                    admin_dir,
+                });
+            }
+            continue;
+        }
+
+        // the repository moved: look for the admin entry of the same name in its new location
+        let candidate = admin_dir
+            .file_name()
+            .map(|name| main_repo.join(".git").join("worktrees").join(name))
+            .filter(|candidate| candidate.is_dir());
+        match candidate {
+            Some(candidate)
+                if links_back(&candidate, &worktree_path)
+                    || !admin_gitdir(&candidate).is_some_and(|gitdir| gitdir.exists()) =>
+            {
+                problems.push(Problem::BrokenLink {
+                    worktree_path,
+                    admin_dir: candidate,
+                })
+            }
+            _ => problems.push(Problem::UnlinkedWorktree { worktree_path }),
+        }
+    }
+    problems
+}
+
+/// Admin entries whose working tree is gone; locked entries are kept like `git worktree prune` does.
+fn find_stale_admin_entries(
+    main_repo: &Path,
+    repaired: &[PathBuf],
+) -> anyhow::Result<Vec<Problem>> {
+    let repo = git2::Repository::open(main_repo)
+        .with_context(|| format!("failed to open repository `{}`", main_repo.display()))?;
+    let names = repo.worktrees().context("failed to list worktrees")?;
+
+    let mut problems = Vec::new();
+    for name in names.iter().flatten() {
+        let admin_dir = repo.path().join("worktrees").join(name);
+        if repaired.iter().any(|path| same_path(path, &admin_dir)) {
+            continue;
+        }
+        let Ok(wt) = repo.find_worktree(name) else {
+            continue;
+        };
+        if wt.validate().is_ok() {
+            continue;
+        }
+        if let Ok(git2::WorktreeLockStatus::Locked(_)) = wt.is_locked() {
+            log::warn!(
+                "keeping locked worktree `{}` with a missing directory",
+                name
+            );
+            continue;
+        }
+        problems.push(Problem::StaleAdminEntry {
+            main_repo: main_repo.to_path_buf(),
+            name: name.to_string(),
+            worktree_path: wt.path().components().collect(),
+        });
+    }
+    Ok(problems)
+}
+
+/// Refuse to delete a directory holding work that may exist nowhere else.
+///
+/// Applies to `--yes` as well: worktrees with unsaved work, worktrees that cannot be inspected but
+/// have files, and files outside any worktree all stop the delete.
+fn ensure_nothing_to_lose(dir: &Path) -> anyhow::Result<()> {
+    if dir.join(".git").is_file() {
+        return ensure_checkout_saved(dir);
+    }
+    let mut walk = WalkDir::new(dir).min_depth(1).into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry.with_context(|| format!("failed to read `{}`", dir.display()))?;
+        let path = entry.path();
+        if entry.file_type().is_dir() && path.join(".git").is_file() {
+            walk.skip_current_dir();
+            ensure_checkout_saved(path)?;
+        } else if !entry.file_type().is_dir() && !worktree::is_slug_metadata(path) {
+            anyhow::bail!(
+                "not deleting `{}`: `{}` is not part of a worktree",
+                dir.display(),
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn ensure_checkout_saved(checkout: &Path) -> anyhow::Result<()> {
+    match git::inspect_unsaved_work(checkout) {
+        Ok(work) if work.is_empty() => Ok(()),
+        Ok(work) => anyhow::bail!(
+            "not deleting `{}`: it has {}",
+            checkout.display(),
+            work.describe()
+        ),
+        Err(err) => {
+            let has_files = WalkDir::new(checkout)
+                .min_depth(1)
+                .into_iter()
+                .flatten()
+                .any(|entry| entry.file_name() != ".git");
+            if has_files {
+                anyhow::bail!(
+                    "not deleting `{}`: could not check it for unsaved work: {:#}",
+                    checkout.display(),
+                    err
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The `.git` file path recorded in an admin entry.
+fn admin_gitdir(admin_dir: &Path) -> Option<PathBuf> {
+    let contents = std::fs::read_to_string(admin_dir.join("gitdir")).ok()?;
+    Some(PathBuf::from(contents.trim()))
+}
+
+fn links_back(admin_dir: &Path, worktree_path: &Path) -> bool {
+    admin_gitdir(admin_dir).is_some_and(|gitdir| same_path(&gitdir, &worktree_path.join(".git")))
+}
+
+/// Point the worktree's `.git` file and the admin entry at each other, like `git worktree repair`.
+fn repair_links(worktree_path: &Path, admin_dir: &Path) -> anyhow::Result<()> {
+    let dot_git = worktree_path.join(".git");
+    std::fs::write(&dot_git, format!("gitdir: {}\n", admin_dir.display()))
+        .with_context(|| format!("failed to write `{}`", dot_git.display()))?;
+    let gitdir = admin_dir.join("gitdir");
+    std::fs::write(&gitdir, format!("{}\n", dot_git.display()))
+        .with_context(|| format!("failed to write `{}`", gitdir.display()))?;
+
+    // libgit2 records the common dir as an absolute path, which breaks when the repository moves
+    let commondir = admin_dir.join("commondir");
+    let recorded = std::fs::read_to_string(&commondir).unwrap_or_default();
+    if !admin_dir.join(recorded.trim()).is_dir() {
+        std::fs::write(&commondir, "../..\n")
+            .with_context(|| format!("failed to write `{}`", commondir.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cmd::worktree::test_util::add_branch_worktree, git::test_util};
+
+    #[test]
+    fn finds_and_fixes_broken_worktrees() {
+        let root = test_util::unique_temp_path("worktree-doctor");
+        let main_repo = root.join("repo");
+        test_util::init_repo(&main_repo);
+        let worktree_root = root.join("worktrees");
+        let slug_dir = worktree::slug_dir_for_repo(&main_repo, &worktree_root);
+        std::fs::create_dir_all(&slug_dir).unwrap();
+        worktree::write_slug_metadata(&slug_dir, &main_repo).expect("metadata");
+
+        add_branch_worktree(&main_repo, &slug_dir.join("deleted"));
+        add_branch_worktree(&main_repo, &slug_dir.join("moved"));
+        add_branch_worktree(&main_repo, &slug_dir.join("healthy"));
+        std::fs::remove_dir_all(slug_dir.join("deleted")).unwrap();
+        std::fs::rename(slug_dir.join("moved"), slug_dir.join("renamed")).unwrap();
+        std::fs::create_dir_all(slug_dir.join("stray")).unwrap();
+        std::fs::write(
+            slug_dir.join("stray").join(".git"),
+            "gitdir: /nonexistent/.git/worktrees/stray\n",
+        )
+        .unwrap();
+        let orphan = worktree_root.join("gone-0000cafe");
+        std::fs::create_dir_all(&orphan).unwrap();
+        worktree::write_slug_metadata(&orphan, &root.join("gone")).expect("metadata");
+
+        let problems = find_problems(&worktree_root, &[]).expect("doctor");
+        let fixes = problems
+            .iter()
+            .map(|problem| (problem.fix(), problem.path().to_path_buf()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fixes,
+            vec![
+                ("delete", orphan.clone()),
+                ("repair", slug_dir.join("renamed")),
+                ("delete", slug_dir.join("stray")),
+                ("prune", slug_dir.join("deleted")),
+            ]
+        );
+
+        for problem in &problems {
+            problem.apply().expect("fix");
+        }
+        assert!(find_problems(&worktree_root, &[])
+            .expect("doctor")
+            .is_empty());
+        assert!(!orphan.exists());
+        let listed = worktree::list_linked_worktree_details(&main_repo).expect("list");
+        let paths = listed
+            .iter()
+            .map(|details| (details.path.clone(), details.prunable))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                (slug_dir.join("healthy"), false),
+                (slug_dir.join("renamed"), false),
+            ]
+        );
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    #[test]
+    fn moved_repos_are_repointed_and_never_deleted() {
+        let root = test_util::unique_temp_path("worktree-doctor-moved");
+        let main_repo = root.join("src/repo");
+        test_util::init_repo(&main_repo);
+        let worktree_root = root.join("worktrees");
+        let slug_dir = worktree::slug_dir_for_repo(&main_repo, &worktree_root);
+        std::fs::create_dir_all(&slug_dir).unwrap();
+        worktree::write_slug_metadata(&slug_dir, &main_repo).expect("metadata");
+        add_branch_worktree(&main_repo, &slug_dir.join("feature"));
+        let moved = root.join("code/repo");
+        std::fs::create_dir_all(root.join("code")).unwrap();
+        std::fs::rename(&main_repo, &moved).unwrap();
+
+        // without a project root to find it in, the slug dir looks orphaned
+        let problems = find_problems(&worktree_root, &[]).expect("doctor");
+        assert_eq!(
+            problems,
+            vec![Problem::OrphanedSlugDir {
+                slug_dir: slug_dir.clone()
+            }]
+        );
+        let err = problems[0].apply().expect_err("worktree has files");
+        assert!(format!("{:#}", err).starts_with("not deleting"));
+        assert!(slug_dir.join("feature/README.md").exists());
+
+        let groups: Vec<config::ProjectGroup> = serde_yaml::from_str(&format!(
+            "- root: {}\n  title: code\n  extract: code/(.*)\n",
+            root.join("code").display()
+        ))
+        .unwrap();
+        let problems = find_problems(&worktree_root, &groups).expect("doctor");
+        assert_eq!(
+            problems,
+            vec![Problem::MovedRepo {
+                slug_dir: slug_dir.clone(),
+                main_repo: moved.clone(),
+            }]
+        );
+        problems[0].apply().expect("repoint");
+
+        assert_eq!(
+            worktree::resolve_slug_dir_repo(&slug_dir),
+            Some(moved.clone())
+        );
+        assert_eq!(
+            find_problems(&worktree_root, &groups).expect("doctor"),
+            vec![]
+        );
+        let listed = worktree::list_linked_worktree_details(&moved).expect("list");
+        assert_eq!(listed.len(), 1);
+        assert!(!listed[0].prunable);
+        assert!(slug_dir.join("feature/README.md").exists());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{argparse, parallel::parallel_map, worktree};
+
+/// Worktrees measured at once; walking is I/O bound, so this does not need to track cores.
+const MAX_WORKERS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UsageRow {
+    label: String,
+    worktrees: usize,
+    usage: worktree::DiskUsage,
+}
+
+pub fn du(args: &argparse::WorktreeDu) -> anyhow::Result<()> {
+    let all_repos = worktree::discover_all_worktrees(args.config.as_deref())?;
+
+    let paths = all_repos
+        .iter()
+        .flat_map(|(_, linked_worktrees)| linked_worktrees.iter().map(|d| d.path.clone()))
+        .collect::<Vec<_>>();
+    let mut sizes = measure_worktrees(&paths).into_iter();
+
+    let mut rows = Vec::new();
+    for (main_repo, linked_worktrees) in all_repos {
+        let mut repo_row = UsageRow {
+            label: main_repo.display().to_string(),
+            worktrees: 0,
+            usage: worktree::DiskUsage::default(),
+        };
+        for (details, usage) in linked_worktrees.into_iter().zip(&mut sizes) {
+            if args.worktrees {
+                rows.push(UsageRow {
+                    label: details.path.display().to_string(),
+                    worktrees: 1,
+                    usage,
+                });
+            }
+            repo_row.worktrees += 1;
+            repo_row.usage += usage;
+        }
+        if !args.worktrees {
+            rows.push(repo_row);
+        }
+    }
+
+    print!("{}", render_rows(rows));
+    Ok(())
+}
+
+/// Disk usage of each path, in the same order, measured on a few threads.
+///
+/// Progress goes to stderr when it is a terminal, since stdout carries the results.
+pub(super) fn measure_worktrees(paths: &[PathBuf]) -> Vec<worktree::DiskUsage> {
+    let show_progress = paths.len() > 1 && std::io::stderr().is_terminal();
+    let done = AtomicUsize::new(0);
+
+    let sizes = parallel_map(paths, MAX_WORKERS, |path| {
+        let usage = measure(path);
+        let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+        if show_progress {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\rmeasuring worktrees {}/{}", done, paths.len());
+            let _ = stderr.flush();
+        }
+        usage
+    });
+    if show_progress {
+        eprint!("\r\x1b[2K");
+    }
+    sizes
+}
+
+fn measure(path: &Path) -> worktree::DiskUsage {
+    if path.is_dir() {
+        worktree::disk_usage(path)
+    } else {
+        worktree::DiskUsage::default()
+    }
+}
+
+/// Largest on-disk usage first, followed by a total.
+fn render_rows(mut rows: Vec<UsageRow>) -> String {
+    rows.sort_by_key(|row| std::cmp::Reverse(row.usage.on_disk));
+    let mut total = UsageRow {
+        label: "TOTAL".to_string(),
+        worktrees: 0,
+        usage: worktree::DiskUsage::default(),
+    };
+    for row in &rows {
+        total.worktrees += row.worktrees;
+        total.usage += row.usage;
+    }
+
+    let header = ["PATH", "WORKTREES", "APPARENT", "ON DISK"]
+        .map(ToOwned::to_owned)
+        .to_vec();
+    let table = std::iter::once(header)
+        .chain(rows.iter().chain([&total]).map(|row| {
+            vec![
+                row.label.clone(),
+                row.worktrees.to_string(),
+                super::format_size(row.usage.apparent),
+                super::format_size(row.usage.on_disk),
+            ]
+        }))
+        .collect::<Vec<_>>();
+    super::render_columns(&table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_util;
+
+    fn row(label: &str, worktrees: usize, on_disk: u64) -> UsageRow {
+        UsageRow {
+            label: label.to_string(),
+            worktrees,
+            usage: worktree::DiskUsage {
+                apparent: on_disk / 2,
+                on_disk,
+            },
+        }
+    }
+
+    #[test]
+    fn measures_in_input_order() {
+        let root = test_util::unique_temp_path("worktree-du");
+        let paths = (0..10)
+            .map(|n| {
+                let path = root.join(n.to_string());
+                std::fs::create_dir_all(&path).unwrap();
+                std::fs::write(path.join("file"), "x".repeat(n * 100)).unwrap();
+                path
+            })
+            .chain([root.join("missing")])
+            .collect::<Vec<_>>();
+
+        let sizes = measure_worktrees(&paths);
+
+        let apparent = sizes.iter().map(|usage| usage.apparent).collect::<Vec<_>>();
+        assert_eq!(
+            apparent,
+            vec![0, 100, 200, 300, 400, 500, 600, 700, 800, 900, 0]
+        );
+        std::fs::remove_dir_all(&root).expect("cleanup dir");
+    }
+
+    #[test]
+    fn rows_are_sorted_by_on_disk_size_with_a_total() {
+        let table = render_rows(vec![row("/src/api", 2, 2048), row("/src/web", 1, 4096)]);
+        let lines = table.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("PATH      WORKTREES  APPARENT"));
+        assert!(lines[1].starts_with("/src/web"));
+        assert!(lines[2].starts_with("/src/api"));
+        assert!(lines[3].starts_with("TOTAL     3"));
+        assert!(lines[3].ends_with("6.0 KiB"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use super::du;
+use crate::{argparse, worktree};
+
+#[derive(Debug, Clone, Serialize)]
+struct WorktreeListEntry {
+    repo: PathBuf,
+    name: String,
+    path: PathBuf,
+    branch: Option<String>,
+    upstream: Option<String>,
+    dirty: bool,
+    /// RFC 3339 date of the HEAD commit
+    last_commit: Option<String>,
+    #[serde(skip)]
+    commit_time: Option<i64>,
+    size_bytes: u64,
+    locked: bool,
+    prunable: bool,
+}
+
+impl WorktreeListEntry {
+    fn new(main_repo: PathBuf, details: worktree::LinkedWorktreeDetails) -> WorktreeListEntry {
+        let branch = details
+            .branch_ref
+            .as_deref()
+            .and_then(|name| name.strip_prefix("refs/heads/"))
+            .map(ToOwned::to_owned);
+        let upstream = super::find_upstream_branch(&details.path, branch.as_deref());
+        let commit_time =
+            super::find_head_commit(&details.path).map(|commit| commit.time.seconds());
+        let dirty = super::find_unsaved_work(&details.path).is_dirty();
+
+        WorktreeListEntry {
+            repo: main_repo,
+            name: details.name,
+            path: details.path,
+            branch,
+            upstream,
+            dirty,
+            last_commit: commit_time
+                .and_then(|time| chrono::DateTime::from_timestamp(time, 0))
+                .map(|time| time.to_rfc3339()),
+            commit_time,
+            // measured for all entries at once by `list`
+            size_bytes: 0,
+            locked: details.locked,
+            prunable: details.prunable,
+        }
+    }
+
+    fn state(&self) -> String {
+        let mut state = vec![if self.dirty { "dirty" } else { "clean" }];
+        if self.locked {
+            state.push("locked");
+        }
+        if self.prunable {
+            state.push("prunable");
+        }
+        state.join(",")
+    }
+
+    fn columns(&self) -> Vec<String> {
+        let repo = self
+            .repo
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.repo.display().to_string());
+        let last_commit = self
+            .commit_time
+            .and_then(|time| chrono::DateTime::from_timestamp(time, 0))
+            .map(|time| {
+                time.with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d")
+                    .to_string()
+            });
+        vec![
+            repo,
+            self.name.clone(),
+            self.branch.clone().unwrap_or_else(|| "-".to_string()),
+            self.upstream.clone().unwrap_or_else(|| "-".to_string()),
+            self.state(),
+            last_commit.unwrap_or_else(|| "-".to_string()),
+            super::format_size(self.size_bytes),
+        ]
+    }
+}
+
+pub fn list(args: &argparse::WorktreeList) -> anyhow::Result<()> {
+    let all_repos = worktree::discover_all_worktrees(args.config.as_deref())?;
+
+    let mut entries = Vec::new();
+    for (main_repo, linked_worktrees) in all_repos {
+        for details in linked_worktrees {
+            entries.push(WorktreeListEntry::new(main_repo.clone(), details));
+        }
+    }
+    let paths = entries
+        .iter()
+        .map(|entry| entry.path.clone())
+        .collect::<Vec<_>>();
+    for (entry, usage) in entries.iter_mut().zip(du::measure_worktrees(&paths)) {
+        entry.size_bytes = usage.apparent;
+    }
+    sort_entries(&mut entries, args.sort);
+
+    if args.json {
+        let out = serde_json::to_string_pretty(&entries)?;
+        println!("{}", out);
+    } else {
+        print!("{}", render_table(&entries));
+    }
+    Ok(())
+}
+
+fn sort_entries(entries: &mut [WorktreeListEntry], sort: argparse::WorktreeSort) {
+    match sort {
+        argparse::WorktreeSort::Repo => {
+            entries.sort_by(|a, b| a.repo.cmp(&b.repo).then_with(|| a.name.cmp(&b.name)))
+        }
+        // oldest first, worktrees without commits at the end
+        argparse::WorktreeSort::Age => {
+            entries.sort_by_key(|entry| (entry.commit_time.is_none(), entry.commit_time))
+        }
+        argparse::WorktreeSort::Size => {
+            entries.sort_by_key(|entry| std::cmp::Reverse(entry.size_bytes))
+        }
+    }
+}
+
+fn render_table(entries: &[WorktreeListEntry]) -> String {
+    let header = [
+        "REPO",
+        "NAME",
+        "BRANCH",
+        "UPSTREAM",
+        "STATE",
+        "LAST COMMIT",
+        "SIZE",
+    ]
+    .map(ToOwned::to_owned)
+    .to_vec();
+    let rows = std::iter::once(header)
+        .chain(entries.iter().map(WorktreeListEntry::columns))
+        .collect::<Vec<_>>();
+    super::render_columns(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, commit_time: Option<i64>, size_bytes: u64) -> WorktreeListEntry {
+        WorktreeListEntry {
+            repo: PathBuf::from("/src/api"),
+            name: name.to_string(),
+            path: PathBuf::from("/wt").join(name),
+            branch: Some(name.to_string()),
+            upstream: None,
+            dirty: false,
+            last_commit: None,
+            commit_time,
+            size_bytes,
+            locked: false,
+            prunable: false,
+        }
+    }
+
+    fn names(entries: &[WorktreeListEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    #[test]
+    fn sorts_by_age_and_size() {
+        let mut entries = vec![
+            entry("new", Some(200), 10),
+            entry("none", None, 30),
+            entry("old", Some(100), 20),
+        ];
+
+        sort_entries(&mut entries, argparse::WorktreeSort::Age);
+        assert_eq!(names(&entries), vec!["old", "new", "none"]);
+
+        sort_entries(&mut entries, argparse::WorktreeSort::Size);
+        assert_eq!(names(&entries), vec!["none", "old", "new"]);
+    }
+
+    #[test]
+    fn table_columns_are_aligned() {
+        let mut locked = entry("feature-long-name", None, 2048);
+        locked.locked = true;
+        let table = render_table(&[entry("wt", None, 0), locked]);
+        let lines = table.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("REPO  NAME               BRANCH"));
+        assert!(lines[2].contains("clean,locked"));
+        assert!(lines[2].ends_with("2.0 KiB"));
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+
+use crate::{argparse, config, worktree};
+
+pub fn lock(args: &argparse::WorktreeLock) -> anyhow::Result<()> {
+    let (main_repo, details) = resolve_target_worktree(args.worktree.as_deref())?;
+    if details.locked {
+        log::warn!("`{}` is already locked", details.name);
+        return Ok(());
+    }
+    worktree::lock_worktree(&main_repo, &details.path, args.reason.as_deref())?;
+    println!("{}", details.path.display());
+    Ok(())
+}
+
+pub fn unlock(args: &argparse::WorktreeUnlock) -> anyhow::Result<()> {
+    let (main_repo, details) = resolve_target_worktree(args.worktree.as_deref())?;
+    if !details.locked {
+        log::warn!("`{}` is not locked", details.name);
+        return Ok(());
+    }
+    worktree::unlock_worktree(&main_repo, &details.path)?;
+    println!("{}", details.path.display());
+    Ok(())
+}
+
+pub fn move_worktree(args: &argparse::WorktreeMove) -> anyhow::Result<()> {
+    let (main_repo, details) = resolve_target_worktree(args.worktree.as_deref())?;
+    let destination = moved_destination(&details.path, &args.new_name)?;
+    super::ensure_destination_missing(&destination)?;
+
+    run_git_worktree(&main_repo, build_move_args(&details.path, &destination))?;
+
+    match config::load_config(args.config.as_deref()) {
+        Ok(cfg) => {
+            let branch = details
+                .branch_ref
+                .as_deref()
+                .and_then(|name| name.strip_prefix("refs/heads/"));
+            let ctx = worktree::setup::SetupContext {
+                main_repo: &main_repo,
+                destination: &destination,
+                worktree_name: &args.new_name,
+                branch,
+            };
+            let repaired = worktree::setup::repair_setup_symlinks(
+                &ctx,
+                cfg.worktree_setup_for(&main_repo),
+                &details.path,
+            );
+            log::debug!("repaired {} setup symlinks", repaired);
+        }
+        Err(err) => log::debug!("not repairing setup symlinks: {:#}", err),
+    }
+
+    println!("{}", destination.display());
+    Ok(())
+}
+
+pub fn migrate(args: &argparse::WorktreeMigrate) -> anyhow::Result<()> {
+    let worktree_roots = worktree::resolve_worktree_roots(args.config.as_deref())?;
+    let cfg = match config::load_config(args.config.as_deref()) {
+        Ok(cfg) => Some(cfg),
+        Err(err) => {
+            log::debug!("not repairing setup symlinks: {:#}", err);
+            None
+        }
+    };
+
+    let mut failures = Vec::new();
+    for worktree_root in worktree_roots {
+        if !worktree_root.is_dir() {
+            log::warn!("worktree root `{}` does not exist", worktree_root.display());
+            continue;
+        }
+        let mut slug_dirs = std::fs::read_dir(&worktree_root)
+            .with_context(|| format!("failed to read `{}`", worktree_root.display()))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect::<Vec<_>>();
+        slug_dirs.sort();
+
+        for slug_dir in slug_dirs {
+            let Some(main_repo) = worktree::resolve_slug_dir_repo(&slug_dir) else {
+                log::warn!(
+                    "could not resolve main repo from slug dir `{}`",
+                    slug_dir.display()
+                );
+                continue;
+            };
+            // the repo's own root may differ from the one it was found under
+            let target_root = worktree::resolve_worktree_root(args.config.as_deref(), &main_repo)
+                .unwrap_or_else(|_| worktree_root.clone());
+            let setup = cfg
+                .as_ref()
+                .map(|cfg| cfg.worktree_setup_for(&main_repo))
+                .unwrap_or_default();
+            match migrate_slug_dir(&target_root, &slug_dir, &main_repo, setup, args.dry_run) {
+                Ok(moves) => {
+                    for (from, to) in moves {
+                        println!("{}\t{}", from.display(), to.display());
+                    }
+                }
+                Err(err) => failures.push(format!("{}: {:#}", slug_dir.display(), err)),
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        anyhow::bail!("failed to migrate some worktrees:\n{}", failures.join("\n"));
+    }
+    Ok(())
+}
+
+/// Move the worktrees in `slug_dir` to the slug directory `main_repo` maps to now.
+///
+/// Directories already in place only get their metadata file. Returns the moves made, or
+/// the moves that would be made with `dry_run`.
+fn migrate_slug_dir(
+    worktree_root: &Path,
+    slug_dir: &Path,
+    main_repo: &Path,
+    setup: &[config::SetupEntry],
+    dry_run: bool,
+) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let expected = worktree::slug_dir_for_repo(main_repo, worktree_root);
+    if same_path(slug_dir, &expected) {
+        if !dry_run {
+            worktree::write_slug_metadata(slug_dir, main_repo)?;
+        }
+        return Ok(Vec::new());
+    }
+
+    let mut children = std::fs::read_dir(slug_dir)
+        .with_context(|| format!("failed to read `{}`", slug_dir.display()))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    children.sort();
+
+    let mut moves = Vec::new();
+    for child in children {
+        match worktree::inspect_repo_worktree(&child) {
+            Ok(Some(info)) if same_path(&info.main_repo_path, main_repo) => {}
+            _ => {
+                log::debug!("leaving `{}` in place", child.display());
+                continue;
+            }
+        }
+        let name = child
+            .file_name()
+            .context("worktree path has no name")?
+            .to_string_lossy()
+            .to_string();
+        let destination = expected.join(&name);
+        super::ensure_destination_missing(&destination)?;
+
+        if !dry_run {
+            std::fs::create_dir_all(&expected)
+                .with_context(|| format!("failed to create `{}`", expected.display()))?;
+            worktree::write_slug_metadata(&expected, main_repo)?;
+            run_git_worktree(main_repo, build_move_args(&child, &destination))?;
+            let ctx = worktree::setup::SetupContext {
+                main_repo,
+                destination: &destination,
+                worktree_name: &name,
+                branch: None,
+            };
+            worktree::setup::repair_setup_symlinks(&ctx, setup, &child);
+        }
+        moves.push((child, destination));
+    }
+
+    if !dry_run && worktree::remove_empty_slug_dir(slug_dir)? {
+        log::debug!("removed old slug dir `{}`", slug_dir.display());
+    }
+    Ok(moves)
+}
+
+/// The named linked worktree of the current repo, or the one the current directory is in.
+pub(super) fn resolve_target_worktree(
+    name: Option<&str>,
+) -> anyhow::Result<(PathBuf, worktree::LinkedWorktreeDetails)> {
+    let cwd = std::env::current_dir().context("failed to read current directory")?;
+    let repo = git2::Repository::discover(&cwd).context("git")?;
+    let repo_workdir = repo
+        .workdir()
+        .context("bare repositories are not supported")?;
+    let main_repo = worktree::resolve_main_repo_path(repo_workdir)
+        .context("failed to resolve main repository path")?;
+
+    let linked_worktrees = worktree::list_linked_worktree_details(&main_repo)?;
+    let found = match name {
+        Some(name) => linked_worktrees
+            .into_iter()
+            .find(|details| details.name == name)
+            .with_context(|| format!("no linked worktree named `{}`", name))?,
+        None => linked_worktrees
+            .into_iter()
+            .find(|details| same_path(&details.path, repo_workdir))
+            .context("not inside a linked worktree, pass --worktree <name>")?,
+    };
+    Ok((main_repo, found))
+}
+
+pub(super) fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// New location next to the current one, so the worktree stays in its slug directory.
+fn moved_destination(current: &Path, new_name: &str) -> anyhow::Result<PathBuf> {
+    super::validate_worktree_name(new_name)?;
+    let parent = current
+        .parent()
+        .with_context(|| format!("`{}` has no parent directory", current.display()))?;
+    Ok(parent.join(new_name))
+}
+
+fn build_move_args(path: &Path, destination: &Path) -> Vec<OsString> {
+    vec![
+        "worktree".into(),
+        "move".into(),
+        path.as_os_str().to_os_string(),
+        destination.as_os_str().to_os_string(),
+    ]
+}
+
+fn run_git_worktree(main_repo: &Path, git_args: Vec<OsString>) -> anyhow::Result<()> {
+    let command = git_args
+        .iter()
+        .take(2)
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(main_repo)
+        .args(&git_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| {
+            format!(
+                "failed to execute `git {}` for repository `{}`",
+                command,
+                main_repo.display()
+            )
+        })?;
+
+    if !status.success() {
+        anyhow::bail!("`git {}` failed in `{}`", command, main_repo.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cmd::worktree::test_util::add_branch_worktree, git::test_util};
+
+    #[test]
+    fn migrate_moves_worktrees_out_of_legacy_slug_dirs() {
+        let root = test_util::unique_temp_path("worktree-migrate");
+        let main_repo = root.join("repo");
+        test_util::init_repo(&main_repo);
+        let worktree_root = root.join("worktrees");
+        let legacy = worktree_root.join("repo-0123456789abcdef");
+        add_branch_worktree(&main_repo, &legacy.join("feature"));
+        assert_eq!(
+            worktree::resolve_slug_dir_repo(&legacy),
+            Some(main_repo.clone())
+        );
+
+        let moves =
+            migrate_slug_dir(&worktree_root, &legacy, &main_repo, &[], true).expect("dry run");
+        assert_eq!(moves.len(), 1);
+        assert!(legacy.join("feature").exists());
+
+        let moves =
+            migrate_slug_dir(&worktree_root, &legacy, &main_repo, &[], false).expect("migrate");
+        let expected = worktree::slug_dir_for_repo(&main_repo, &worktree_root);
+        assert_eq!(
+            moves,
+            vec![(legacy.join("feature"), expected.join("feature"))]
+        );
+        assert!(!legacy.exists());
+        assert_eq!(
+            worktree::read_slug_metadata(&expected).expect("metadata"),
+            Some(worktree::SlugMetadata {
+                main_repo: main_repo.clone()
+            })
+        );
+        let listed = worktree::list_linked_worktree_details(&main_repo).expect("list");
+        assert_eq!(listed[0].path, expected.join("feature"));
+
+        let moves = migrate_slug_dir(&worktree_root, &expected, &main_repo, &[], false)
+            .expect("already migrated");
+        assert!(moves.is_empty());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    #[test]
+    fn moved_worktrees_stay_in_their_slug_dir() {
+        let got = moved_destination(Path::new("/wt/slug/feature"), "renamed").unwrap();
+        assert_eq!(got, PathBuf::from("/wt/slug/renamed"));
+        assert!(moved_destination(Path::new("/wt/slug/feature"), "../escape").is_err());
+        assert!(moved_destination(Path::new("/wt/slug/feature"), "").is_err());
+    }
+
+    #[test]
+    fn lock_unlock_and_move_update_worktree_state() {
+        let root = test_util::unique_temp_path("worktree-manage");
+        let main_repo = root.join("repo");
+        test_util::init_repo(&main_repo);
+        let path = root.join("slug").join("feature");
+        add_branch_worktree(&main_repo, &path);
+        let details = |name: &str| {
+            worktree::list_linked_worktree_details(&main_repo)
+                .expect("list")
+                .into_iter()
+                .find(|d| d.name == name)
+        };
+
+        worktree::lock_worktree(&main_repo, &path, Some("on a usb disk")).expect("lock");
+        assert!(details("feature").expect("listed").locked);
+        worktree::unlock_worktree(&main_repo, &path).expect("unlock");
+        assert!(!details("feature").expect("listed").locked);
+
+        let destination = moved_destination(&path, "renamed").unwrap();
+        run_git_worktree(&main_repo, build_move_args(&path, &destination)).expect("move");
+        assert!(details("feature").is_none());
+        assert_eq!(details("renamed").expect("listed").path, destination);
+        assert!(destination.join("README.md").exists());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::Context;
+
+use super::CreatePlan;
+use crate::{argparse, git};
+
+/// Fetch `<remote>/<branch>` and plan a local branch of the same name tracking it.
+pub(super) fn plan_remote_branch(
+    repo: &git2::Repository,
+    spec: &str,
+    args: &argparse::WorktreeCreate,
+) -> anyhow::Result<CreatePlan> {
+    let (remote, branch) = git::split_remote_branch(repo, &format!("refs/remotes/{}", spec))?;
+    let tracking_ref = format!("refs/remotes/{}/{}", remote, branch);
+    git::fetch(
+        repo,
+        &remote,
+        &[&format!("+refs/heads/{}:{}", branch, tracking_ref)],
+    )?;
+    repo.find_reference(&tracking_ref)
+        .with_context(|| format!("branch `{}` does not exist on `{}`", branch, remote))?;
+
+    Ok(CreatePlan {
+        name: args
+            .name
+            .clone()
+            .unwrap_or_else(|| worktree_name_from_ref(&branch)),
+        branch: Some(args.branch.clone().unwrap_or(branch)),
+        commitish: Some(tracking_ref),
+        track: true,
+        existing_branch: false,
+    })
+}
+
+/// Fetch an arbitrary ref such as `refs/pull/123/head` and plan a branch at the fetched commit.
+pub(super) fn plan_fetched_ref(
+    repo: &git2::Repository,
+    ref_name: &str,
+    args: &argparse::WorktreeCreate,
+) -> anyhow::Result<CreatePlan> {
+    let remote = args.remote.as_deref().unwrap_or("origin");
+    git::fetch(repo, remote, &[ref_name])?;
+    let commit = git::fetch_head_commit(repo, ref_name)?;
+
+    let name = args
+        .name
+        .clone()
+        .unwrap_or_else(|| worktree_name_from_ref(ref_name));
+    let mut plan = CreatePlan::from_args(args, name);
+    plan.commitish = Some(commit.to_string());
+    Ok(plan)
+}
+
+/// Directory-safe name for a ref: `refs/pull/123/head` becomes `pull-123`, `alice/fix` `alice-fix`.
+fn worktree_name_from_ref(ref_name: &str) -> String {
+    let short = ref_name.strip_prefix("refs/").unwrap_or(ref_name);
+    let short = short.strip_prefix("heads/").unwrap_or(short);
+    let short = short.strip_suffix("/head").unwrap_or(short);
+    short.replace('/', "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cmd::worktree::test_util::{add_worktree, create_args},
+        git::test_util,
+    };
+
+    /// A local clone of a bare repository that has `alice/fix` and `refs/pull/7/head`.
+    fn setup_remote(name: &str) -> (std::path::PathBuf, git2::Repository) {
+        let root = test_util::unique_temp_path(name);
+        let upstream = test_util::init_repo(&root.join("upstream"));
+        let fix = test_util::commit_file(&upstream, "fix.txt", "fix\n", "fix");
+
+        let bare = git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(
+                root.join("upstream").to_str().unwrap(),
+                &root.join("bare.git"),
+            )
+            .expect("bare clone");
+        bare.reference("refs/heads/alice/fix", fix, false, "test")
+            .unwrap();
+        bare.reference("refs/pull/7/head", fix, false, "test")
+            .unwrap();
+
+        let local = test_util::init_repo(&root.join("local"));
+        local
+            .remote("origin", root.join("bare.git").to_str().unwrap())
+            .unwrap();
+        (root, local)
+    }
+
+    #[test]
+    fn names_worktrees_after_refs() {
+        assert_eq!(worktree_name_from_ref("refs/pull/123/head"), "pull-123");
+        assert_eq!(worktree_name_from_ref("refs/heads/alice/fix"), "alice-fix");
+        assert_eq!(worktree_name_from_ref("feature"), "feature");
+    }
+
+    #[test]
+    fn remote_branch_worktrees_track_the_fetched_branch() {
+        let (root, local) = setup_remote("create-from-remote");
+
+        let plan = plan_remote_branch(&local, "origin/alice/fix", &create_args()).expect("plan");
+        assert_eq!(plan.name, "alice-fix");
+        assert_eq!(plan.branch.as_deref(), Some("alice/fix"));
+        assert!(plan_remote_branch(&local, "origin/missing", &create_args()).is_err());
+
+        let destination = root.join("wt");
+        add_worktree(&root.join("local"), &destination, &plan).expect("add worktree");
+
+        assert!(destination.join("fix.txt").exists());
+        assert_eq!(
+            git::local_branch_upstream(&local, "alice/fix").as_deref(),
+            Some("refs/remotes/origin/alice/fix")
+        );
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+
+    #[test]
+    fn fetched_refs_create_a_branch_at_the_commit() {
+        let (root, local) = setup_remote("create-fetch-ref");
+
+        let plan = plan_fetched_ref(&local, "refs/pull/7/head", &create_args()).expect("plan");
+        assert_eq!(plan.name, "pull-7");
+        assert_eq!(plan.branch.as_deref(), Some("pull-7"));
+
+        let destination = root.join("wt");
+        add_worktree(&root.join("local"), &destination, &plan).expect("add worktree");
+
+        assert!(destination.join("fix.txt").exists());
+        assert!(git::local_branch_upstream(&local, "pull-7").is_none());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    ffi::OsStr,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+
+use super::manage::resolve_target_worktree;
+use crate::{argparse, config};
+
+pub fn sparse(args: &argparse::WorktreeSparse) -> anyhow::Result<()> {
+    let (dirs, removing) = match args {
+        argparse::WorktreeSparse::Add(dirs) => (dirs, false),
+        argparse::WorktreeSparse::Remove(dirs) => (dirs, true),
+    };
+    let (_, details) = resolve_target_worktree(dirs.worktree.as_deref())?;
+    if removing {
+        remove_dirs(&details.path, &dirs.dirs)?;
+    } else {
+        add_dirs(&details.path, &dirs.dirs)?;
+    }
+    println!("{}", details.path.display());
+    Ok(())
+}
+
+/// Directories of a `worktrees.sparse` profile.
+pub(super) fn profile_dirs(
+    cfg: Option<&config::ShelfConfig>,
+    profile: &str,
+) -> anyhow::Result<Vec<String>> {
+    let cfg = cfg.with_context(|| format!("sparse profile `{}` needs a config", profile))?;
+    cfg.worktrees
+        .sparse
+        .get(profile)
+        .cloned()
+        .with_context(|| format!("no sparse profile `{}` in `worktrees.sparse`", profile))
+}
+
+/// Restrict a worktree added with `--no-checkout` to `dirs` and check those out.
+///
+/// The sparse-checkout file is per worktree, so the main checkout keeps all its files.
+pub(super) fn checkout_sparse(worktree_path: &Path, dirs: &[String]) -> anyhow::Result<()> {
+    run_git(worktree_path, ["sparse-checkout", "set", "--cone"], dirs)?;
+    run_git(worktree_path, ["read-tree", "-mu", "HEAD"], &[])?;
+    Ok(())
+}
+
+fn add_dirs(worktree_path: &Path, dirs: &[String]) -> anyhow::Result<()> {
+    sparse_dirs(worktree_path)?;
+    run_git(worktree_path, ["sparse-checkout", "add"], dirs)?;
+    Ok(())
+}
+
+/// Cone mode has no removal, so the remaining directories are set again.
+fn remove_dirs(worktree_path: &Path, dirs: &[String]) -> anyhow::Result<()> {
+    let current = sparse_dirs(worktree_path)?;
+    let remove = dirs
+        .iter()
+        .map(|dir| dir.trim_matches('/'))
+        .collect::<Vec<_>>();
+    if let Some(missing) = remove
+        .iter()
+        .find(|dir| !current.iter().any(|current| current == *dir))
+    {
+        anyhow::bail!(
+            "`{}` is not checked out in `{}`",
+            missing,
+            worktree_path.display()
+        );
+    }
+    let remaining = current
+        .into_iter()
+        .filter(|dir| !remove.contains(&dir.as_str()))
+        .collect::<Vec<_>>();
+    run_git(
+        worktree_path,
+        ["sparse-checkout", "set", "--cone"],
+        &remaining,
+    )?;
+    Ok(())
+}
+
+/// Cone-mode directories of a sparse worktree.
+fn sparse_dirs(worktree_path: &Path) -> anyhow::Result<Vec<String>> {
+    let repo = git2::Repository::open(worktree_path).context("git")?;
+    let sparse_file = repo.path().join("info").join("sparse-checkout");
+    if !sparse_file.is_file() {
+        anyhow::bail!(
+            "`{}` is not a sparse worktree, create it with `--sparse`",
+            worktree_path.display()
+        );
+    }
+    let listed = run_git(worktree_path, ["sparse-checkout", "list"], &[])?;
+    Ok(listed.lines().map(ToString::to_string).collect())
+}
+
+/// Run git inside a worktree and return its stdout.
+fn run_git<const N: usize>(
+    worktree_path: &Path,
+    command: [&str; N],
+    dirs: &[String],
+) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .args(command)
+        .args(dirs.iter().map(OsStr::new))
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| {
+            format!(
+                "failed to execute `git {}` in `{}`",
+                command.join(" "),
+                worktree_path.display()
+            )
+        })?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "`git {}` failed in `{}`",
+            command.join(" "),
+            worktree_path.display()
+        );
+    }
+    String::from_utf8(output.stdout).context("git output is not utf-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cmd::worktree::{create_steps, test_util::branch_plan, transaction::CreateTransaction},
+        git::test_util,
+        worktree,
+    };
+
+    fn checked_out(path: &Path) -> Vec<&'static str> {
+        ["README.md", "api/main.rs", "web/index.js", "docs/guide.md"]
+            .into_iter()
+            .filter(|file| path.join(file).exists())
+            .collect()
+    }
+
+    #[test]
+    fn sparse_worktrees_check_out_only_their_profile() {
+        let root = test_util::unique_temp_path("worktree-sparse");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+        for dir in ["api", "web", "docs"] {
+            std::fs::create_dir_all(main_repo.join(dir)).unwrap();
+        }
+        test_util::commit_file(&repo, "api/main.rs", "fn main() {}\n", "api");
+        test_util::commit_file(&repo, "web/index.js", "\n", "web");
+        test_util::commit_file(&repo, "docs/guide.md", "\n", "docs");
+        let cfg: config::ShelfConfig =
+            serde_yaml::from_str("worktrees:\n  sparse:\n    backend: [api]\n").unwrap();
+        assert!(profile_dirs(Some(&cfg), "frontend").is_err());
+        let dirs = profile_dirs(Some(&cfg), "backend").unwrap();
+
+        let destination = root.join("worktrees/slug/feature");
+        let plan = branch_plan("feature");
+        let mut transaction = CreateTransaction::default();
+        create_steps(
+            &main_repo,
+            &destination,
+            &plan,
+            Some(&dirs),
+            None,
+            &mut transaction,
+        )
+        .expect("create sparse worktree");
+
+        assert_eq!(checked_out(&destination), vec!["README.md", "api/main.rs"]);
+        assert_eq!(checked_out(&main_repo).len(), 4);
+        let listed = worktree::list_linked_worktree_details(&main_repo).expect("list");
+        assert_eq!(listed[0].branch_ref.as_deref(), Some("refs/heads/feature"));
+
+        add_dirs(&destination, &["web".to_string()]).expect("add");
+        remove_dirs(&destination, &["api/".to_string()]).expect("remove");
+        assert_eq!(checked_out(&destination), vec!["README.md", "web/index.js"]);
+        assert!(remove_dirs(&destination, &["docs".to_string()]).is_err());
+        assert!(add_dirs(&main_repo, &["docs".to_string()]).is_err());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::{git, worktree};
+
+/// Something `worktree create` did that has to be undone when a later step fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Step {
+    /// Topmost directory that did not exist before
+    CreatedDir(PathBuf),
+    CreatedBranch {
+        main_repo: PathBuf,
+        name: String,
+    },
+    AddedWorktree {
+        main_repo: PathBuf,
+        path: PathBuf,
+    },
+    /// Target of a `worktrees.setup` entry
+    SetupEntry(PathBuf),
+}
+
+impl Step {
+    fn undo(&self) -> anyhow::Result<()> {
+        match self {
+            Step::CreatedDir(path) => std::fs::remove_dir_all(path)
+                .with_context(|| format!("failed to remove `{}`", path.display())),
+            Step::CreatedBranch { main_repo, name } => {
+                let repo = git2::Repository::open(main_repo).context("git")?;
+                git::delete_local_branch(&repo, name)
+            }
+            Step::AddedWorktree { main_repo, path } => {
+                worktree::remove_worktree(main_repo, path, true)
+            }
+            // removed with the worktree anyway, but symlinks must not be followed into the main repo
+            Step::SetupEntry(path) if path.symlink_metadata().is_ok() => {
+                worktree::setup::remove_path(path)
+            }
+            Step::SetupEntry(_) => Ok(()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Step::CreatedDir(path) => format!("directory `{}`", path.display()),
+            Step::CreatedBranch { name, .. } => format!("branch `{}`", name),
+            Step::AddedWorktree { path, .. } => format!("worktree `{}`", path.display()),
+            Step::SetupEntry(path) => format!("setup entry `{}`", path.display()),
+        }
+    }
+}
+
+/// Steps of a worktree creation so far, undone in reverse order when it fails.
+#[derive(Debug, Default)]
+pub(super) struct CreateTransaction {
+    steps: Vec<Step>,
+}
+
+impl CreateTransaction {
+    pub(super) fn record(&mut self, step: Step) {
+        self.steps.push(step);
+    }
+
+    /// `create_dir_all` that records the topmost directory it had to create.
+    pub(super) fn create_dir_all(&mut self, dir: &Path) -> anyhow::Result<()> {
+        let topmost_missing = dir
+            .ancestors()
+            .take_while(|ancestor| !ancestor.exists())
+            .last()
+            .map(Path::to_path_buf);
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create directory `{}`", dir.display()))?;
+        if let Some(created) = topmost_missing {
+            self.record(Step::CreatedDir(created));
+        }
+        Ok(())
+    }
+
+    /// Undo every recorded step, newest first, and add what was undone to `err`.
+    pub(super) fn rollback(self, err: anyhow::Error) -> anyhow::Error {
+        if self.steps.is_empty() {
+            return err;
+        }
+        let report = self
+            .steps
+            .iter()
+            .rev()
+            .map(|step| match step.undo() {
+                Ok(()) => format!("  removed {}", step.describe()),
+                Err(undo_err) => format!("  could not remove {}: {:#}", step.describe(), undo_err),
+            })
+            .collect::<Vec<_>>();
+        err.context(format!("rolled back:\n{}", report.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_util;
+
+    #[test]
+    fn rollback_undoes_steps_in_reverse_and_reports_them() {
+        let root = test_util::unique_temp_path("create-rollback");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+
+        let mut transaction = CreateTransaction::default();
+        transaction
+            .create_dir_all(&root.join("worktrees/slug"))
+            .expect("create dirs");
+        repo.branch("feature", &head, false).unwrap();
+        transaction.record(Step::CreatedBranch {
+            main_repo: main_repo.clone(),
+            name: "feature".to_string(),
+        });
+        let link = root.join("worktrees/slug/.envrc");
+        std::os::unix::fs::symlink(main_repo.join("README.md"), &link).unwrap();
+        transaction.record(Step::SetupEntry(link));
+
+        let err = transaction.rollback(anyhow::anyhow!("hook failed"));
+
+        let message = format!("{:#}", err);
+        assert!(message.starts_with("rolled back:\n  removed setup entry"));
+        assert!(message.contains("\n  removed branch `feature`\n  removed directory"));
+        assert!(message.ends_with(": hook failed"));
+        assert!(!root.join("worktrees").exists());
+        assert!(main_repo.join("README.md").exists());
+        assert!(repo
+            .find_branch("feature", git2::BranchType::Local)
+            .is_err());
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
+}
@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
@@ -29,6 +32,12 @@ pub struct ProjectGroup {
     pub color: Option<NamedColor>,
     #[serde(default)]
     pub recurse: bool,
+    /// Worktree root for repos in this group instead of `worktrees.root`
+    #[serde(default)]
+    pub worktree_root: Option<PathBuf>,
+    /// Replaces `worktrees.setup` for repos in this group
+    #[serde(default)]
+    pub worktree_setup: Option<Vec<SetupEntry>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -38,15 +47,126 @@ pub struct ManualDirectory {
     pub label: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookFailure {
+    /// Log the failure and keep going
+    #[default]
+    Warn,
+    /// Stop; a failed `post_create` hook rolls back the new worktree and a failed
+    /// `pre_remove` hook keeps the worktree in place
+    #[serde(alias = "rollback")]
+    Abort,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorktreeHook {
+    pub command: String,
+    /// Seconds before the hook is killed
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub on_failure: HookFailure,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WorktreeHooks {
+    #[serde(default)]
+    pub post_create: Vec<WorktreeHook>,
+    #[serde(default)]
+    pub pre_remove: Vec<WorktreeHook>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum SetupMode {
+    #[default]
+    Symlink,
+    Copy,
+    /// Copy-on-write clone, falling back to a plain copy when unsupported
+    Reflink,
+    /// Render `source` with `{{branch}}`, `{{worktree}}`, `{{repo}}` and `{{port_offset}}`
+    Template,
+}
+
+/// What happens to the branch of a worktree removed by `worktree cleanup`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum BranchDeletion {
+    #[default]
+    Keep,
+    /// Delete the local branch once it is merged or its upstream is gone
+    Local,
+    /// Also delete the merged branch on its upstream remote
+    Remote,
+}
+
+/// A path prepared in each new worktree; a bare string is a symlink entry.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum SetupEntry {
+    Path(String),
+    Detailed {
+        path: String,
+        #[serde(default)]
+        mode: SetupMode,
+        /// File in the main repo to read from, defaults to `path`
+        #[serde(default)]
+        source: Option<String>,
+    },
+}
+
+impl SetupEntry {
+    pub fn path(&self) -> &str {
+        match self {
+            SetupEntry::Path(path) => path,
+            SetupEntry::Detailed { path, .. } => path,
+        }
+    }
+
+    pub fn mode(&self) -> SetupMode {
+        match self {
+            SetupEntry::Path(_) => SetupMode::Symlink,
+            SetupEntry::Detailed { mode, .. } => *mode,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        match self {
+            SetupEntry::Path(path) => path,
+            SetupEntry::Detailed { path, source, .. } => source.as_deref().unwrap_or(path),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct WorktreeConfig {
     #[serde(default)]
     pub root: Option<PathBuf>,
     #[serde(default)]
-    pub setup: Vec<String>,
+    pub setup: Vec<SetupEntry>,
+    #[serde(default)]
+    pub hooks: WorktreeHooks,
+    #[serde(default)]
+    pub delete_branch: BranchDeletion,
+    /// Branch for `worktree create <name>` without `--branch`, e.g. `{{user}}/{{name}}`
+    #[serde(default)]
+    pub branch_template: Option<String>,
+    /// Worktree directory name for `worktree create <name>`, e.g. `{{date}}-{{name}}`
+    #[serde(default)]
+    pub name_template: Option<String>,
+    /// Sparse-checkout profiles for `worktree create --sparse`, each a list of cone-mode directories
+    #[serde(default)]
+    pub sparse: BTreeMap<String, Vec<String>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BranchConfig {
+    #[serde(default)]
+    pub protect: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ShelfConfig {
     #[serde(default)]
     pub projects: Vec<ProjectGroup>,
@@ -54,6 +174,8 @@ pub struct ShelfConfig {
     pub directories: Vec<ManualDirectory>,
     #[serde(default)]
     pub worktrees: WorktreeConfig,
+    #[serde(default)]
+    pub branches: BranchConfig,
 }
 
 fn read_config(config_path: &Path) -> anyhow::Result<ShelfConfig> {
@@ -63,6 +185,23 @@ fn read_config(config_path: &Path) -> anyhow::Result<ShelfConfig> {
         .with_context(|| format!("could not parse config at `{:?}`", config_path))
 }
 
+impl ShelfConfig {
+    /// Project group with the longest root containing `repo`.
+    pub fn project_group_for(&self, repo: &Path) -> Option<&ProjectGroup> {
+        self.projects
+            .iter()
+            .filter(|group| !group.root.as_os_str().is_empty() && repo.starts_with(&group.root))
+            .max_by_key(|group| group.root.components().count())
+    }
+
+    /// Setup entries for new worktrees of `repo`, from its project group when it has any.
+    pub fn worktree_setup_for(&self, repo: &Path) -> &[SetupEntry] {
+        self.project_group_for(repo)
+            .and_then(|group| group.worktree_setup.as_deref())
+            .unwrap_or(&self.worktrees.setup)
+    }
+}
+
 pub fn load_config(config_override: Option<&Path>) -> anyhow::Result<ShelfConfig> {
     if let Some(config_path) = config_override {
         read_config(config_path)
@@ -179,5 +318,154 @@ mod tests {
         let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
 
         assert_eq!(config.worktrees.root, Some(PathBuf::from("/tmp/worktrees")));
+        assert_eq!(config.worktrees.delete_branch, BranchDeletion::Keep);
+    }
+
+    #[test]
+    fn loadconfig_with_branch_deletion() {
+        let conf = r###"
+            worktrees:
+              delete_branch: remote
+        "###;
+
+        let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
+
+        assert_eq!(config.worktrees.delete_branch, BranchDeletion::Remote);
+    }
+
+    #[test]
+    fn loadconfig_with_naming_templates() {
+        let conf = r###"
+            worktrees:
+              branch_template: "{{user}}/{{name}}"
+        "###;
+
+        let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
+
+        assert_eq!(
+            config.worktrees.branch_template.as_deref(),
+            Some("{{user}}/{{name}}")
+        );
+        assert_eq!(config.worktrees.name_template, None);
+    }
+
+    #[test]
+    fn project_groups_override_worktree_setup() {
+        let conf = r###"
+            projects:
+              - title: src
+                root: /src
+                extract: src/(.*)
+              - title: work
+                root: /src/work
+                extract: work/(.*)
+                worktree_root: /mnt/big/worktrees
+                worktree_setup:
+                  - node_modules
+            worktrees:
+              setup:
+                - .envrc
+        "###;
+
+        let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
+
+        let group = config.project_group_for(Path::new("/src/work/api"));
+        assert_eq!(group.map(|group| group.title.as_str()), Some("work"));
+        assert_eq!(
+            config.worktree_setup_for(Path::new("/src/work/api"))[0].path(),
+            "node_modules"
+        );
+        assert_eq!(
+            config.worktree_setup_for(Path::new("/src/workshop"))[0].path(),
+            ".envrc"
+        );
+        assert!(config.project_group_for(Path::new("/opt/api")).is_none());
+    }
+
+    #[test]
+    fn loadconfig_with_sparse_profiles() {
+        let conf = r###"
+            worktrees:
+              sparse:
+                backend:
+                  - services/api
+                  - libs/common
+        "###;
+
+        let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
+
+        assert_eq!(
+            config.worktrees.sparse["backend"],
+            vec!["services/api", "libs/common"]
+        );
+    }
+
+    #[test]
+    fn loadconfig_with_mixed_setup_entries() {
+        let conf = r###"
+            worktrees:
+              setup:
+                - .envrc
+                - path: node_modules
+                  mode: reflink
+                - path: .env
+                  mode: template
+                  source: .env.template
+        "###;
+
+        let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
+        let setup = &config.worktrees.setup;
+
+        assert_eq!(setup.len(), 3);
+        assert_eq!(setup[0], SetupEntry::Path(".envrc".to_string()));
+        assert_eq!(setup[0].mode(), SetupMode::Symlink);
+        assert_eq!(setup[1].mode(), SetupMode::Reflink);
+        assert_eq!(setup[1].source(), "node_modules");
+        assert_eq!(setup[2].path(), ".env");
+        assert_eq!(setup[2].mode(), SetupMode::Template);
+        assert_eq!(setup[2].source(), ".env.template");
+    }
+
+    #[test]
+    fn loadconfig_with_worktree_hooks() {
+        let conf = r###"
+            worktrees:
+              root: /tmp/worktrees
+              hooks:
+                post_create:
+                  - command: direnv allow
+                  - command: npm ci
+                    timeout: 600
+                    on_failure: rollback
+                pre_remove:
+                  - command: docker compose down
+                    on_failure: abort
+        "###;
+
+        let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
+        let hooks = &config.worktrees.hooks;
+
+        assert_eq!(hooks.post_create.len(), 2);
+        assert_eq!(hooks.post_create[0].on_failure, HookFailure::Warn);
+        assert_eq!(hooks.post_create[0].timeout, None);
+        assert_eq!(hooks.post_create[1].timeout, Some(600));
+        assert_eq!(hooks.post_create[1].on_failure, HookFailure::Abort);
+        assert_eq!(hooks.pre_remove[0].command, "docker compose down");
+        assert_eq!(hooks.pre_remove[0].on_failure, HookFailure::Abort);
+    }
+
+    #[test]
+    fn loadconfig_with_branch_protection() {
+        let conf = r###"
+            branches:
+              protect:
+                - ^release/
+                - ^develop$
+        "###;
+
+        let config: ShelfConfig = serde_yaml::from_str(conf).unwrap();
+
+        assert_eq!(config.branches.protect, vec!["^release/", "^develop$"]);
+        assert!(config.worktrees.root.is_none());
     }
 }
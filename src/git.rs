@@ -1,3 +1,9 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    process::{Command, Stdio},
+};
+
 use anyhow::Context;
 
 pub(crate) struct GitRef<'r>(git2::Reference<'r>);
@@ -28,13 +34,22 @@ impl<'r> From<git2::Branch<'r>> for GitRef<'r> {
     }
 }
 
+/// How a local branch relates to its configured upstream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UpstreamState {
+    Tracking { ahead: usize, behind: usize },
+    Gone,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct GitBranch {
     pub(crate) name: String,
     pub(crate) ref_name: String,
     pub(crate) branch_type: git2::BranchType,
     pub(crate) head: bool,
+    pub(crate) upstream: Option<UpstreamState>,
 }
+
 impl PartialOrd for GitBranch {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -58,6 +73,26 @@ impl Ord for GitBranch {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct GitTag {
+    pub(crate) name: String,
+    pub(crate) annotated: bool,
+}
+
+/// A `stash@{n}` entry; `index` is the position in the stash reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GitStash {
+    pub(crate) index: usize,
+    pub(crate) message: String,
+}
+
+/// A `HEAD@{n}` position from the HEAD reflog.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GitReflogEntry {
+    pub(crate) index: usize,
+    pub(crate) message: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct GitCommit {
     pub(crate) id: git2::Oid,
@@ -68,14 +103,13 @@ pub(crate) struct GitCommit {
 
 impl PartialOrd for GitCommit {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.time.partial_cmp(&other.time)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for GitCommit {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other)
-            .expect("invalid comparison between commits")
+        self.time.cmp(&other.time)
     }
 }
 
@@ -85,12 +119,520 @@ impl GitCommit {
             .get()
             .peel_to_commit()
             .context("unable to peel commit")?;
+        Ok(GitCommit::from_commit(&commit))
+    }
+
+    pub(crate) fn from_commit(commit: &git2::Commit<'_>) -> GitCommit {
         let author = commit.author();
-        Ok(GitCommit {
+        GitCommit {
             id: commit.id(),
             message: String::from_utf8_lossy(commit.message_bytes()).into_owned(),
             time: commit.time(),
             author: String::from_utf8_lossy(author.name_bytes()).into_owned(),
+        }
+    }
+}
+
+/// Split a remote-tracking ref like `refs/remotes/origin/feature/x` into `("origin", "feature/x")`.
+pub(crate) fn split_remote_branch(
+    repo: &git2::Repository,
+    ref_name: &str,
+) -> anyhow::Result<(String, String)> {
+    let remote = repo
+        .branch_remote_name(ref_name)
+        .with_context(|| format!("could not find remote for `{}`", ref_name))?;
+    let remote = remote
+        .as_str()
+        .context("remote name was not utf-8")?
+        .to_string();
+    let branch = ref_name
+        .strip_prefix("refs/remotes/")
+        .and_then(|r| r.strip_prefix(remote.as_str()))
+        .and_then(|r| r.strip_prefix('/'))
+        .with_context(|| format!("`{}` is not a branch of remote `{}`", ref_name, remote))?
+        .to_string();
+    Ok((remote, branch))
+}
+
+pub(crate) fn delete_local_branch(repo: &git2::Repository, name: &str) -> anyhow::Result<()> {
+    let mut branch = repo
+        .find_branch(name, git2::BranchType::Local)
+        .with_context(|| format!("could not find local branch `{}`", name))?;
+    branch
+        .delete()
+        .with_context(|| format!("could not delete local branch `{}`", name))
+}
+
+/// Delete a branch on the remote with `git push --delete`, which also drops the tracking ref.
+///
+/// This shells out so the user's credential helpers and ssh agent are used for the push.
+pub(crate) fn delete_remote_branch(
+    repo_path: &Path,
+    remote: &str,
+    branch: &str,
+) -> anyhow::Result<()> {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["push", remote, "--delete", branch])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| {
+            format!(
+                "failed to execute `git push --delete` for repository `{}`",
+                repo_path.display()
+            )
+        })?;
+
+    if !status.success() {
+        anyhow::bail!("`git push {} --delete {}` failed", remote, branch);
+    }
+    Ok(())
+}
+
+/// Upstream remote-tracking ref of a local branch, if it has one configured and present.
+pub(crate) fn local_branch_upstream(repo: &git2::Repository, name: &str) -> Option<String> {
+    let branch = repo.find_branch(name, git2::BranchType::Local).ok()?;
+    let upstream = branch.upstream().ok()?;
+    let upstream = upstream.get().name()?.to_string();
+    Some(upstream)
+}
+
+/// True when a local branch tracks an upstream whose remote-tracking ref no longer exists.
+pub(crate) fn is_upstream_gone(repo: &git2::Repository, name: &str) -> bool {
+    let local_ref = format!("refs/heads/{}", name);
+    let Ok(upstream) = repo.branch_upstream_name(&local_ref) else {
+        return false;
+    };
+    let Some(upstream) = upstream.as_str() else {
+        return false;
+    };
+    repo.find_reference(upstream).is_err()
+}
+
+/// Ahead/behind counts against the upstream of a local branch, or `Gone` if it was deleted.
+pub(crate) fn upstream_state(
+    repo: &git2::Repository,
+    branch: &git2::Branch<'_>,
+) -> Option<UpstreamState> {
+    let name = branch.name().ok().flatten()?;
+    if is_upstream_gone(repo, name) {
+        return Some(UpstreamState::Gone);
+    }
+    let upstream = branch.upstream().ok()?;
+    let local = branch.get().target()?;
+    let remote = upstream.get().target()?;
+    match repo.graph_ahead_behind(local, remote) {
+        Ok((ahead, behind)) => Some(UpstreamState::Tracking { ahead, behind }),
+        Err(err) => {
+            log::debug!("could not compare `{}` with its upstream: {}", name, err);
+            None
+        }
+    }
+}
+
+/// Work in a checkout that only exists there and would be lost with its directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub(crate) struct UnsavedWork {
+    pub(crate) modified: usize,
+    pub(crate) untracked: usize,
+    /// Commits on HEAD not reachable from any remote-tracking ref or other local branch
+    pub(crate) unpushed: usize,
+    /// Stash entries recorded on this checkout's branch
+    pub(crate) stashes: usize,
+}
+
+impl UnsavedWork {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &UnsavedWork::default()
+    }
+
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.modified > 0 || self.untracked > 0
+    }
+
+    /// Human readable list such as `2 modified files, 1 unpushed commit`.
+    pub(crate) fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        for (count, singular, plural) in [
+            (self.modified, "modified file", "modified files"),
+            (self.untracked, "untracked file", "untracked files"),
+            (self.unpushed, "unpushed commit", "unpushed commits"),
+            (self.stashes, "stash", "stashes"),
+        ] {
+            match count {
+                0 => {}
+                1 => parts.push(format!("1 {}", singular)),
+                n => parts.push(format!("{} {}", n, plural)),
+            }
+        }
+        parts.join(", ")
+    }
+}
+
+pub(crate) fn inspect_unsaved_work(path: &Path) -> anyhow::Result<UnsavedWork> {
+    let repo = git2::Repository::open(path)
+        .with_context(|| format!("failed to open `{}`", path.display()))?;
+    let mut work = UnsavedWork::default();
+
+    let mut status_opts = git2::StatusOptions::new();
+    status_opts
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .renames_head_to_index(true)
+        .renames_index_to_workdir(true)
+        .include_ignored(false);
+    let statuses = repo
+        .statuses(Some(&mut status_opts))
+        .context("failed to inspect status")?;
+    for entry in statuses.iter() {
+        if entry.status() == git2::Status::WT_NEW {
+            work.untracked += 1;
+        } else {
+            work.modified += 1;
+        }
+    }
+
+    let head = match repo.head() {
+        Ok(head) => head,
+        // unborn HEAD, nothing committed yet
+        Err(_) => return Ok(work),
+    };
+    let head_branch = head
+        .is_branch()
+        .then(|| head.name().map(ToOwned::to_owned))
+        .flatten();
+    if let Some(head_id) = head.target() {
+        work.unpushed = count_unpushed(&repo, head_id, head_branch.as_deref())?;
+    }
+    work.stashes = count_branch_stashes(&repo, head.shorthand().filter(|_| head.is_branch()))?;
+
+    Ok(work)
+}
+
+fn count_unpushed(
+    repo: &git2::Repository,
+    head: git2::Oid,
+    head_branch: Option<&str>,
+) -> anyhow::Result<usize> {
+    let mut walk = repo.revwalk().context("failed to walk history")?;
+    walk.push(head)?;
+    for reference in repo.references()?.flatten() {
+        let Some(name) = reference.name() else {
+            continue;
+        };
+        let other_branch = name.starts_with("refs/heads/") && Some(name) != head_branch;
+        if !(name.starts_with("refs/remotes/") || other_branch) {
+            continue;
+        }
+        if let Ok(commit) = reference.peel_to_commit() {
+            walk.hide(commit.id())?;
+        }
+    }
+    Ok(walk.count())
+}
+
+fn count_branch_stashes(repo: &git2::Repository, branch: Option<&str>) -> anyhow::Result<usize> {
+    if repo.find_reference("refs/stash").is_err() {
+        return Ok(0);
+    }
+    let branch = branch.unwrap_or("(no branch)");
+    let wip = format!("WIP on {}:", branch);
+    let custom = format!("On {}:", branch);
+    let reflog = repo.reflog("refs/stash")?;
+    Ok(reflog
+        .iter()
+        .filter(|entry| {
+            entry
+                .message()
+                .map(|m| m.starts_with(&wip) || m.starts_with(&custom))
+                .unwrap_or(false)
         })
+        .count())
+}
+
+/// Local branch names checked out in the repository or any of its linked worktrees.
+pub(crate) fn checked_out_branches(repo: &git2::Repository) -> HashSet<String> {
+    let mut names = HashSet::new();
+    let mut insert_head = |repo: &git2::Repository| {
+        if let Some(name) = repo
+            .head()
+            .ok()
+            .filter(|head| head.is_branch())
+            .and_then(|head| head.shorthand().map(ToOwned::to_owned))
+        {
+            names.insert(name);
+        }
+    };
+    insert_head(repo);
+
+    let worktrees = match repo.worktrees() {
+        Ok(worktrees) => worktrees,
+        Err(err) => {
+            log::debug!("could not list worktrees: {}", err);
+            return names;
+        }
+    };
+    for name in worktrees.iter().flatten() {
+        let Ok(worktree) = repo.find_worktree(name) else {
+            continue;
+        };
+        if let Ok(wt_repo) = git2::Repository::open_from_worktree(&worktree) {
+            insert_head(&wt_repo);
+        }
+    }
+    names
+}
+
+/// Working directory of a repository, or its git dir when bare.
+pub(crate) fn repo_root(repo: &git2::Repository) -> &Path {
+    repo.workdir().unwrap_or_else(|| repo.path())
+}
+
+/// Fetch `refspecs` from a configured remote.
+///
+/// Credentials come from the ssh agent or the configured credential helpers, like `git fetch`.
+pub(crate) fn fetch(
+    repo: &git2::Repository,
+    remote: &str,
+    refspecs: &[&str],
+) -> anyhow::Result<()> {
+    let mut remote_handle = repo
+        .find_remote(remote)
+        .with_context(|| format!("could not find remote `{}`", remote))?;
+    let config = repo.config().context("get config")?;
+
+    let mut callbacks = git2::RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        if allowed.contains(git2::CredentialType::SSH_KEY) {
+            git2::Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if allowed.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+            git2::Cred::credential_helper(&config, url, username)
+        } else {
+            git2::Cred::default()
+        }
+    });
+    let mut options = git2::FetchOptions::new();
+    options.remote_callbacks(callbacks);
+
+    remote_handle
+        .fetch(refspecs, Some(&mut options), None)
+        .with_context(|| format!("failed to fetch {:?} from `{}`", refspecs, remote))
+}
+
+/// Commit recorded in `FETCH_HEAD` for `ref_name` by the last fetch.
+pub(crate) fn fetch_head_commit(
+    repo: &git2::Repository,
+    ref_name: &str,
+) -> anyhow::Result<git2::Oid> {
+    let mut found = None;
+    repo.fetchhead_foreach(|name, _url, oid, _is_merge| {
+        if name == ref_name {
+            found = Some(*oid);
+            return false;
+        }
+        true
+    })
+    .or_else(|err| match found {
+        // stopping the iteration early is reported as an error
+        Some(_) => Ok(()),
+        None => Err(err),
+    })
+    .context("could not read FETCH_HEAD")?;
+    found.with_context(|| format!("`{}` was not fetched", ref_name))
+}
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    use std::path::{Path, PathBuf};
+
+    pub(crate) fn unique_temp_path(name: &str) -> PathBuf {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("valid clock")
+            .as_nanos();
+        std::env::temp_dir().join(format!("shelf-{}-{}", name, nanos))
+    }
+
+    /// Create a repository with a single commit on `main`.
+    pub(crate) fn init_repo(path: &Path) -> git2::Repository {
+        let mut opts = git2::RepositoryInitOptions::new();
+        opts.initial_head("main");
+        let repo = git2::Repository::init_opts(path, &opts).expect("init repo");
+        {
+            let mut config = repo.config().expect("repo config");
+            config.set_str("user.name", "Alice").expect("set user.name");
+            config
+                .set_str("user.email", "alice@example.com")
+                .expect("set user.email");
+        }
+        commit_file(&repo, "README.md", "hello\n", "initial commit");
+        repo
+    }
+
+    /// Write a file in the work tree and commit it on top of HEAD.
+    pub(crate) fn commit_file(
+        repo: &git2::Repository,
+        file: &str,
+        contents: &str,
+        message: &str,
+    ) -> git2::Oid {
+        let workdir = repo.workdir().expect("non-bare repo");
+        std::fs::write(workdir.join(file), contents).expect("write file");
+        let mut index = repo.index().expect("index");
+        index.add_path(Path::new(file)).expect("add path");
+        index.write().expect("write index");
+        let tree_id = index.write_tree().expect("write tree");
+        let tree = repo.find_tree(tree_id).expect("find tree");
+        let sig = repo.signature().expect("signature");
+        let parent = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+        let parents = parent.iter().collect::<Vec<_>>();
+        repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+            .expect("commit")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_util, *};
+
+    #[test]
+    fn delete_local_branch_removes_ref() {
+        let path = test_util::unique_temp_path("git-delete-branch");
+        let repo = test_util::init_repo(&path);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch("feature", &head, false).unwrap();
+
+        delete_local_branch(&repo, "feature").expect("delete branch");
+
+        assert!(repo
+            .find_branch("feature", git2::BranchType::Local)
+            .is_err());
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn delete_local_branch_refuses_checked_out_branch() {
+        let path = test_util::unique_temp_path("git-delete-head");
+        let repo = test_util::init_repo(&path);
+
+        assert!(delete_local_branch(&repo, "main").is_err());
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn upstream_gone_only_when_tracking_ref_is_missing() {
+        let path = test_util::unique_temp_path("git-upstream-gone");
+        let repo = test_util::init_repo(&path);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        for name in ["tracked", "gone", "untracked"] {
+            repo.branch(name, &head, false).unwrap();
+        }
+        let mut config = repo.config().unwrap();
+        for name in ["tracked", "gone"] {
+            config
+                .set_str(&format!("branch.{}.remote", name), "origin")
+                .unwrap();
+            config
+                .set_str(
+                    &format!("branch.{}.merge", name),
+                    &format!("refs/heads/{}", name),
+                )
+                .unwrap();
+        }
+        repo.reference("refs/remotes/origin/tracked", head.id(), false, "test")
+            .unwrap();
+
+        assert!(!is_upstream_gone(&repo, "tracked"));
+        assert!(is_upstream_gone(&repo, "gone"));
+        assert!(!is_upstream_gone(&repo, "untracked"));
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn upstream_state_counts_ahead_and_behind() {
+        let path = test_util::unique_temp_path("git-upstream-state");
+        let repo = test_util::init_repo(&path);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        let base = repo.head().unwrap().peel_to_commit().unwrap().id();
+        test_util::commit_file(&repo, "a.txt", "a\n", "local work");
+        repo.reference("refs/remotes/origin/main", base, false, "test")
+            .unwrap();
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("branch.main.remote", "origin").unwrap();
+            config
+                .set_str("branch.main.merge", "refs/heads/main")
+                .unwrap();
+        }
+
+        let main = repo.find_branch("main", git2::BranchType::Local).unwrap();
+        assert_eq!(
+            upstream_state(&repo, &main),
+            Some(UpstreamState::Tracking {
+                ahead: 1,
+                behind: 0
+            })
+        );
+
+        repo.find_reference("refs/remotes/origin/main")
+            .unwrap()
+            .delete()
+            .unwrap();
+        assert_eq!(upstream_state(&repo, &main), Some(UpstreamState::Gone));
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn unsaved_work_counts_changes_commits_and_stashes() {
+        let path = test_util::unique_temp_path("git-unsaved-work");
+        let mut repo = test_util::init_repo(&path);
+        let base = repo.head().unwrap().peel_to_commit().unwrap().id();
+        repo.reference("refs/remotes/origin/main", base, false, "test")
+            .unwrap();
+        assert!(inspect_unsaved_work(&path).unwrap().is_empty());
+
+        test_util::commit_file(&repo, "a.txt", "a\n", "local only");
+        std::fs::write(path.join("a.txt"), "changed\n").unwrap();
+        let sig = repo.signature().unwrap();
+        repo.stash_save(&sig, "parked", None).unwrap();
+        std::fs::write(path.join("README.md"), "edited\n").unwrap();
+        std::fs::write(path.join("new.txt"), "new\n").unwrap();
+
+        let work = inspect_unsaved_work(&path).unwrap();
+        assert_eq!(
+            work,
+            UnsavedWork {
+                modified: 1,
+                untracked: 1,
+                unpushed: 1,
+                stashes: 1,
+            }
+        );
+        assert_eq!(
+            work.describe(),
+            "1 modified file, 1 untracked file, 1 unpushed commit, 1 stash"
+        );
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
+    }
+
+    #[test]
+    fn split_remote_branch_handles_slashes() {
+        let path = test_util::unique_temp_path("git-split-remote");
+        let repo = test_util::init_repo(&path);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+
+        let (remote, branch) =
+            split_remote_branch(&repo, "refs/remotes/origin/alice/feature-x").expect("split");
+
+        assert_eq!(remote, "origin");
+        assert_eq!(branch, "alice/feature-x");
+        std::fs::remove_dir_all(&path).expect("cleanup repo");
     }
 }
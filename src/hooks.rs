@@ -0,0 +1,212 @@
+use std::{
+    os::{fd::AsFd, unix::process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use crate::config::{HookFailure, WorktreeHook};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Describes the worktree a hook runs for; exported to the hook as `SHELF_*` variables.
+#[derive(Debug, Clone)]
+pub(crate) struct HookEnv {
+    pub(crate) main_repo: PathBuf,
+    pub(crate) worktree: PathBuf,
+    pub(crate) name: String,
+    pub(crate) branch: Option<String>,
+}
+
+impl HookEnv {
+    fn vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("SHELF_REPO", self.main_repo.display().to_string()),
+            ("SHELF_WORKTREE", self.worktree.display().to_string()),
+            ("SHELF_WORKTREE_NAME", self.name.clone()),
+            ("SHELF_BRANCH", self.branch.clone().unwrap_or_default()),
+        ]
+    }
+}
+
+/// Run each hook in order inside the worktree.
+///
+/// Failures of `warn` hooks are logged and skipped; the first failing `abort` hook stops the
+/// run and is returned so the caller can undo whatever the hooks were guarding.
+pub(crate) fn run_hooks(stage: &str, hooks: &[WorktreeHook], env: &HookEnv) -> anyhow::Result<()> {
+    for hook in hooks {
+        log::debug!("{} hook: `{}`", stage, hook.command);
+        if let Err(err) = run_hook(hook, env) {
+            match hook.on_failure {
+                HookFailure::Warn => {
+                    log::warn!("{} hook `{}` failed: {:#}", stage, hook.command, err);
+                }
+                HookFailure::Abort => {
+                    return Err(err.context(format!("{} hook `{}` failed", stage, hook.command)));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_hook(hook: &WorktreeHook, env: &HookEnv) -> anyhow::Result<()> {
+    // stdout carries the path for shell wrappers, so hook output goes to stderr
+    let stderr = std::io::stderr()
+        .as_fd()
+        .try_clone_to_owned()
+        .context("failed to duplicate stderr")?;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&hook.command)
+        .current_dir(&env.worktree)
+        .envs(env.vars())
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(stderr))
+        .stderr(Stdio::inherit())
+        // its own process group, so a timeout can stop everything the hook started
+        .process_group(0)
+        .spawn()
+        .with_context(|| format!("failed to spawn hook in `{}`", env.worktree.display()))?;
+
+    let status = match hook.timeout {
+        Some(secs) => wait_with_timeout(&mut child, Duration::from_secs(secs))?,
+        None => child.wait().context("failed to wait for hook")?,
+    };
+    if !status.success() {
+        anyhow::bail!("hook exited with {}", status);
+    }
+    Ok(())
+}
+
+fn wait_with_timeout(
+    child: &mut std::process::Child,
+    timeout: Duration,
+) -> anyhow::Result<std::process::ExitStatus> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait().context("failed to wait for hook")? {
+            return Ok(status);
+        }
+        if Instant::now() >= deadline {
+            // the hook was spawned as a group leader, so its pid is the group id
+            let pgid = child.id() as libc::pid_t;
+            if unsafe { libc::killpg(pgid, libc::SIGKILL) } != 0 {
+                log::debug!(
+                    "failed to kill timed out hook: {}",
+                    std::io::Error::last_os_error()
+                );
+            }
+            let _ = child.wait();
+            anyhow::bail!("hook timed out after {}s", timeout.as_secs());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Hook environment for an existing worktree, deriving the name from its directory.
+pub(crate) fn env_for_worktree(main_repo: &Path, worktree: &Path, branch: Option<&str>) -> HookEnv {
+    HookEnv {
+        main_repo: main_repo.to_path_buf(),
+        worktree: worktree.to_path_buf(),
+        name: worktree
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        branch: branch.map(ToOwned::to_owned),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_util::unique_temp_path;
+
+    fn hook(command: &str, on_failure: HookFailure, timeout: Option<u64>) -> WorktreeHook {
+        WorktreeHook {
+            command: command.to_string(),
+            timeout,
+            on_failure,
+        }
+    }
+
+    fn env(dir: &Path) -> HookEnv {
+        HookEnv {
+            main_repo: PathBuf::from("/src/repo"),
+            worktree: dir.to_path_buf(),
+            name: "feature-123".to_string(),
+            branch: Some("alice/feature-123".to_string()),
+        }
+    }
+
+    #[test]
+    fn hooks_run_in_worktree_with_env() {
+        let dir = unique_temp_path("hooks-env");
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let hooks = vec![hook(
+            "printf '%s|%s|%s' \"$SHELF_REPO\" \"$SHELF_WORKTREE_NAME\" \"$SHELF_BRANCH\" > out.txt",
+            HookFailure::Abort,
+            None,
+        )];
+
+        run_hooks("post-create", &hooks, &env(&dir)).expect("hooks succeed");
+
+        let out = std::fs::read_to_string(dir.join("out.txt")).expect("hook output");
+        assert_eq!(out, "/src/repo|feature-123|alice/feature-123");
+        std::fs::remove_dir_all(&dir).expect("cleanup dir");
+    }
+
+    #[test]
+    fn warn_failures_do_not_stop_later_hooks() {
+        let dir = unique_temp_path("hooks-warn");
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let hooks = vec![
+            hook("exit 3", HookFailure::Warn, None),
+            hook("touch ran", HookFailure::Abort, None),
+        ];
+
+        run_hooks("post-create", &hooks, &env(&dir)).expect("warn failures are ignored");
+
+        assert!(dir.join("ran").exists());
+        std::fs::remove_dir_all(&dir).expect("cleanup dir");
+    }
+
+    #[test]
+    fn abort_failures_stop_the_run() {
+        let dir = unique_temp_path("hooks-abort");
+        std::fs::create_dir_all(&dir).expect("create dir");
+        let hooks = vec![
+            hook("exit 1", HookFailure::Abort, None),
+            hook("touch ran", HookFailure::Abort, None),
+        ];
+
+        let err = run_hooks("post-create", &hooks, &env(&dir)).expect_err("abort failure");
+
+        assert!(format!("{:#}", err).contains("`exit 1` failed"));
+        assert!(!dir.join("ran").exists());
+        std::fs::remove_dir_all(&dir).expect("cleanup dir");
+    }
+
+    #[test]
+    fn hooks_are_killed_after_timeout() {
+        let dir = unique_temp_path("hooks-timeout");
+        std::fs::create_dir_all(&dir).expect("create dir");
+        // the subshell is a child of `sh`, so only killing the whole group stops it
+        let hooks = vec![hook(
+            "(sleep 2; touch marker); exit 0",
+            HookFailure::Abort,
+            Some(1),
+        )];
+
+        let started = Instant::now();
+        let err = run_hooks("post-create", &hooks, &env(&dir)).expect_err("timeout");
+
+        assert!(format!("{:#}", err).contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        std::thread::sleep(Duration::from_secs(2));
+        assert!(!dir.join("marker").exists());
+        std::fs::remove_dir_all(&dir).expect("cleanup dir");
+    }
+}
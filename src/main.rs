@@ -1,20 +1,28 @@
+use std::process::ExitCode;
+
 use crate::tmux::get_tmux;
 
 mod argparse;
 
 mod cmd {
+    pub mod branch;
+    pub mod foreach;
     pub mod gitjump;
     pub mod project;
+    pub mod status;
     pub mod worktree;
 }
 mod config;
 mod git;
+mod hooks;
+mod parallel;
+mod prompt;
 mod scan;
 mod skim_style;
 mod tmux;
 mod worktree;
 
-fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<ExitCode> {
     color_backtrace::install();
     let args = argparse::get_args();
     setup_logger(args.verbose);
@@ -29,6 +37,15 @@ fn main() -> anyhow::Result<()> {
             argparse::WorktreePicker::Create(args) => cmd::worktree::create(args),
             argparse::WorktreePicker::Cleanup(args) => cmd::worktree::cleanup(args),
             argparse::WorktreePicker::CleanupAll(args) => cmd::worktree::cleanup_all(args),
+            argparse::WorktreePicker::List(args) => cmd::worktree::list(args),
+            argparse::WorktreePicker::Switch(args) => cmd::worktree::switch(args),
+            argparse::WorktreePicker::Lock(args) => cmd::worktree::lock(args),
+            argparse::WorktreePicker::Unlock(args) => cmd::worktree::unlock(args),
+            argparse::WorktreePicker::Move(args) => cmd::worktree::move_worktree(args),
+            argparse::WorktreePicker::Migrate(args) => cmd::worktree::migrate(args),
+            argparse::WorktreePicker::Du(args) => cmd::worktree::du(args),
+            argparse::WorktreePicker::Doctor(args) => cmd::worktree::doctor(args),
+            argparse::WorktreePicker::Sparse(args) => cmd::worktree::sparse(args),
         },
         argparse::SubCommand::Test(_) => {
             if let Some(tmux) = get_tmux() {
@@ -44,10 +61,22 @@ fn main() -> anyhow::Result<()> {
             Ok(())
         }
         argparse::SubCommand::GitJump(cmd) => cmd::gitjump::jump(cmd),
+        argparse::SubCommand::Branch(cmd) => match cmd {
+            argparse::BranchPicker::Prune(args) => cmd::branch::prune(args),
+        },
+        argparse::SubCommand::Status(args) => cmd::status::status(args),
+        argparse::SubCommand::Foreach(args) => cmd::foreach::foreach(args),
     }
-    .map_err(|e| {
+    .map(|()| ExitCode::SUCCESS)
+    .or_else(|e| {
+        if e.is::<cmd::status::NeedsAttention>() {
+            return Ok(ExitCode::FAILURE);
+        }
         log::error!("{:?}", e);
-        anyhow::anyhow!("unrecoverable {} failure", clap::crate_name!())
+        Err(anyhow::anyhow!(
+            "unrecoverable {} failure",
+            clap::crate_name!()
+        ))
     })
 }
 
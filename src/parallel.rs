@@ -0,0 +1,54 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+/// Apply `f` to every item on at most `jobs` threads; results are in the order of `items`.
+pub fn parallel_map<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(
+        std::iter::repeat_with(|| None)
+            .take(items.len())
+            .collect::<Vec<_>>(),
+    );
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.max(1).min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = f(item);
+                results.lock().expect("parallel results")[index] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .expect("parallel results")
+        .into_iter()
+        .map(|result| result.expect("every item was mapped"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_input_order_for_any_job_count() {
+        let items = (0..50).collect::<Vec<u64>>();
+        let expected = items.iter().map(|n| n * n).collect::<Vec<_>>();
+
+        for jobs in [0, 1, 3, 100] {
+            assert_eq!(parallel_map(&items, jobs, |n| n * n), expected);
+        }
+        assert!(parallel_map(&[] as &[u64], 4, |n| *n).is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{BufRead, Write};
+
+use anyhow::Context;
+
+/// Ask a yes/no question on stderr and read the answer from stdin.
+///
+/// stdout is reserved for paths consumed by shell wrappers, so prompts never go there.
+pub(crate) fn confirm(question: &str) -> anyhow::Result<bool> {
+    let answer = read_line(&format!("{} [y/N] ", question))?;
+    Ok(parse_confirmation(&answer))
+}
+
+/// Print a prompt on stderr and return the trimmed line typed by the user.
+pub(crate) fn read_line(prompt: &str) -> anyhow::Result<String> {
+    let mut stderr = std::io::stderr();
+    write!(stderr, "{}", prompt).context("failed to write prompt")?;
+    stderr.flush().context("failed to flush prompt")?;
+
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("failed to read answer from stdin")?;
+    Ok(line.trim().to_string())
+}
+
+/// Number the options on stderr and ask for one; `None` when the answer is empty or not a listed number.
+pub(crate) fn choose(question: &str, options: &[&str]) -> anyhow::Result<Option<usize>> {
+    for (number, option) in options.iter().enumerate() {
+        eprintln!("  {}) {}", number + 1, option);
+    }
+    let answer = read_line(&format!("{} [1-{}] ", question, options.len()))?;
+    Ok(parse_choice(&answer, options.len()))
+}
+
+fn parse_choice(answer: &str, count: usize) -> Option<usize> {
+    let number = answer.trim().parse::<usize>().ok()?;
+    (1..=count).contains(&number).then(|| number - 1)
+}
+
+fn parse_confirmation(answer: &str) -> bool {
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_choice, parse_confirmation};
+
+    #[test]
+    fn confirmation_accepts_yes_variants() {
+        assert!(parse_confirmation("y"));
+        assert!(parse_confirmation("Yes"));
+        assert!(parse_confirmation(" YES "));
+    }
+
+    #[test]
+    fn confirmation_defaults_to_no() {
+        assert!(!parse_confirmation(""));
+        assert!(!parse_confirmation("n"));
+        assert!(!parse_confirmation("yep"));
+    }
+
+    #[test]
+    fn choice_must_be_a_listed_number() {
+        assert_eq!(parse_choice("1", 3), Some(0));
+        assert_eq!(parse_choice(" 3 ", 3), Some(2));
+        assert_eq!(parse_choice("", 3), None);
+        assert_eq!(parse_choice("0", 3), None);
+        assert_eq!(parse_choice("4", 3), None);
+        assert_eq!(parse_choice("two", 3), None);
+    }
+}
@@ -39,10 +39,18 @@ pub(crate) fn dirty_style() -> Style {
     Style::new().fg(Color::Red).bold()
 }
 
+pub(crate) fn unsaved_work_style() -> Style {
+    Style::new().fg(Color::Red)
+}
+
 pub(crate) fn detached_style() -> Style {
     Style::new().fg(Color::Yellow).bold()
 }
 
+pub(crate) fn size_style() -> Style {
+    Style::new().fg(COMMIT_GREY)
+}
+
 pub(crate) fn locked_style() -> Style {
     Style::new().fg(Color::Purple)
 }
@@ -51,6 +59,18 @@ pub(crate) fn prunable_style() -> Style {
     Style::new().fg(MUTED_GREY)
 }
 
+pub(crate) fn ahead_style() -> Style {
+    Style::new().fg(Color::Green)
+}
+
+pub(crate) fn behind_style() -> Style {
+    Style::new().fg(Color::Red)
+}
+
+pub(crate) fn upstream_gone_style() -> Style {
+    Style::new().fg(MUTED_GREY).italic()
+}
+
 pub(crate) fn commit_message_style() -> Style {
     Style::new().fg(COMMIT_GREY)
 }
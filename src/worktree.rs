@@ -1,13 +1,47 @@
 use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
+    collections::HashSet,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
     path::{Path, PathBuf},
-    process::{Command, Stdio},
 };
 
 use anyhow::Context;
+use serde::{Deserialize, Serialize};
 
-use crate::config::load_config;
+use crate::{config::load_config, git};
+
+pub(crate) mod setup;
+
+/// Git config key overriding the worktree root of a single repository.
+const WORKTREE_ROOT_GIT_CONFIG: &str = "shelf.worktreeRoot";
+
+/// Written into each slug directory so the owning repo is known without inspecting children.
+const SLUG_METADATA_FILE: &str = ".shelf-slug.yml";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Unit of `st_blocks`, independent of the filesystem block size.
+const DISK_BLOCK_SIZE: u64 = 512;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SlugMetadata {
+    pub(crate) main_repo: PathBuf,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct DiskUsage {
+    /// Sum of file lengths
+    pub(crate) apparent: u64,
+    /// Allocated blocks, smaller than `apparent` for sparse files and larger for many small ones
+    pub(crate) on_disk: u64,
+}
+
+impl std::ops::AddAssign for DiskUsage {
+    fn add_assign(&mut self, other: DiskUsage) {
+        self.apparent += other.apparent;
+        self.on_disk += other.on_disk;
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct WorktreeInfo {
@@ -31,22 +65,71 @@ pub(crate) struct LinkedWorktreeDetails {
     pub(crate) prunable: bool,
 }
 
-pub(crate) fn resolve_worktree_root(config_override: Option<&Path>) -> anyhow::Result<PathBuf> {
+/// Worktree root for the worktrees of `main_repo`.
+///
+/// The repo's git config `shelf.worktreeRoot` wins, then the `worktree_root` of the project
+/// group containing the repo, then `worktrees.root`.
+pub(crate) fn resolve_worktree_root(
+    config_override: Option<&Path>,
+    main_repo: &Path,
+) -> anyhow::Result<PathBuf> {
+    if let Some(root) = git_config_worktree_root(main_repo) {
+        return Ok(root);
+    }
     let config = load_config(config_override)?;
-    if let Some(root) = config.worktrees.root {
+    if let Some(root) = config
+        .project_group_for(main_repo)
+        .and_then(|group| group.worktree_root.clone())
+    {
         return Ok(root);
     }
+    config
+        .worktrees
+        .root
+        .ok_or_else(|| missing_root_error(config_override))
+}
 
-    if let Some(config_override) = config_override {
-        anyhow::bail!(
+/// Every configured worktree root, for commands that scan all worktrees.
+///
+/// Roots set only through a repo's `shelf.worktreeRoot` cannot be found this way.
+pub(crate) fn resolve_worktree_roots(
+    config_override: Option<&Path>,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let config = load_config(config_override)?;
+    let mut roots = Vec::new();
+    let configured = config.worktrees.root.iter().chain(
+        config
+            .projects
+            .iter()
+            .filter_map(|group| group.worktree_root.as_ref()),
+    );
+    for root in configured {
+        if !roots.contains(root) {
+            roots.push(root.clone());
+        }
+    }
+    if roots.is_empty() {
+        return Err(missing_root_error(config_override));
+    }
+    Ok(roots)
+}
+
+fn git_config_worktree_root(main_repo: &Path) -> Option<PathBuf> {
+    let repo = git2::Repository::open(main_repo).ok()?;
+    let config = repo.config().ok()?;
+    config.get_path(WORKTREE_ROOT_GIT_CONFIG).ok()
+}
+
+fn missing_root_error(config_override: Option<&Path>) -> anyhow::Error {
+    match config_override {
+        Some(config_override) => anyhow::anyhow!(
             "missing `worktrees.root` in config `{}`",
             config_override.display()
-        );
+        ),
+        None => anyhow::anyhow!(
+            "missing `worktrees.root` in default config; set it in ~/.config/shelf/shelf.yml or pass --config"
+        ),
     }
-
-    anyhow::bail!(
-        "missing `worktrees.root` in default config; set it in ~/.config/shelf/shelf.yml or pass --config"
-    )
 }
 
 pub(crate) fn inspect_repo_worktree(repo_path: &Path) -> anyhow::Result<Option<WorktreeInfo>> {
@@ -85,35 +168,147 @@ pub(crate) fn list_linked_worktrees(main_repo_path: &Path) -> anyhow::Result<Vec
         .collect())
 }
 
+/// Linked worktrees of a repository, sorted by path.
 pub(crate) fn list_linked_worktree_details(
     main_repo_path: &Path,
 ) -> anyhow::Result<Vec<LinkedWorktreeDetails>> {
-    let output = Command::new("git")
-        .arg("-C")
-        .arg(main_repo_path)
-        .args(["worktree", "list", "--porcelain"])
-        .stdin(Stdio::null())
-        .output()
-        .with_context(|| {
-            format!(
-                "failed to execute `git worktree list --porcelain` for `{}`",
-                main_repo_path.display()
-            )
-        })?;
+    let repo = open_main_repo(main_repo_path)?;
+    let names = repo
+        .worktrees()
+        .with_context(|| format!("failed to list worktrees of `{}`", main_repo_path.display()))?;
+
+    let mut entries = Vec::new();
+    for name in names.iter().flatten() {
+        match repo.find_worktree(name) {
+            Ok(worktree) => entries.push(linked_worktree_details(&repo, &worktree)),
+            Err(err) => log::warn!("failed to open worktree `{}`: {}", name, err),
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn linked_worktree_details(
+    repo: &git2::Repository,
+    worktree: &git2::Worktree,
+) -> LinkedWorktreeDetails {
+    let path: PathBuf = worktree.path().components().collect();
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| path.display().to_string());
+
+    // the admin dir HEAD is readable even when the working tree is gone
+    let admin_dir = repo
+        .path()
+        .join("worktrees")
+        .join(worktree.name().unwrap_or_default());
+    let branch_ref = std::fs::read_to_string(admin_dir.join("HEAD"))
+        .ok()
+        .and_then(|head| head.trim().strip_prefix("ref: ").map(ToOwned::to_owned));
+
+    LinkedWorktreeDetails {
+        detached: branch_ref.is_none(),
+        path,
+        name,
+        branch_ref,
+        locked: matches!(
+            worktree.is_locked(),
+            Ok(git2::WorktreeLockStatus::Locked(_))
+        ),
+        prunable: worktree.validate().is_err(),
+    }
+}
 
-    if !output.status.success() {
+/// Remove a linked worktree and its admin entry, like `git worktree remove`.
+///
+/// Locked worktrees are refused, and so are worktrees with uncommitted changes unless
+/// `force` is set. A working tree that is already gone only has its admin entry pruned.
+pub(crate) fn remove_worktree(
+    main_repo_path: &Path,
+    worktree_path: &Path,
+    force: bool,
+) -> anyhow::Result<()> {
+    let repo = open_main_repo(main_repo_path)?;
+    let worktree = find_worktree_by_path(&repo, worktree_path)?;
+    if let Ok(git2::WorktreeLockStatus::Locked(reason)) = worktree.is_locked() {
         anyhow::bail!(
-            "`git worktree list --porcelain` failed for `{}`",
-            main_repo_path.display()
+            "`{}` is locked{}",
+            worktree_path.display(),
+            reason.map(|r| format!(": {}", r)).unwrap_or_default()
         );
     }
+    if !force && worktree_path.exists() {
+        let unsaved = git::inspect_unsaved_work(worktree_path)?;
+        if unsaved.is_dirty() {
+            anyhow::bail!(
+                "`{}` has uncommitted changes, use --force to remove it anyway",
+                worktree_path.display()
+            );
+        }
+    }
 
-    let text = String::from_utf8(output.stdout).context("worktree list output was not utf-8")?;
-    let entries = parse_worktree_list_porcelain(&text)
-        .into_iter()
-        .filter(|entry| entry.path != main_repo_path)
-        .collect();
-    Ok(entries)
+    worktree
+        .prune(Some(
+            git2::WorktreePruneOptions::new()
+                .valid(true)
+                .working_tree(true),
+        ))
+        .with_context(|| format!("failed to remove worktree `{}`", worktree_path.display()))
+}
+
+pub(crate) fn lock_worktree(
+    main_repo_path: &Path,
+    worktree_path: &Path,
+    reason: Option<&str>,
+) -> anyhow::Result<()> {
+    let repo = open_main_repo(main_repo_path)?;
+    find_worktree_by_path(&repo, worktree_path)?
+        .lock(reason)
+        .with_context(|| format!("failed to lock worktree `{}`", worktree_path.display()))
+}
+
+pub(crate) fn unlock_worktree(main_repo_path: &Path, worktree_path: &Path) -> anyhow::Result<()> {
+    let repo = open_main_repo(main_repo_path)?;
+    find_worktree_by_path(&repo, worktree_path)?
+        .unlock()
+        .with_context(|| format!("failed to unlock worktree `{}`", worktree_path.display()))
+}
+
+fn open_main_repo(main_repo_path: &Path) -> anyhow::Result<git2::Repository> {
+    git2::Repository::open(main_repo_path)
+        .with_context(|| format!("failed to open repository `{}`", main_repo_path.display()))
+}
+
+fn find_worktree_by_path(
+    repo: &git2::Repository,
+    worktree_path: &Path,
+) -> anyhow::Result<git2::Worktree> {
+    let names = repo.worktrees().context("failed to list worktrees")?;
+    let wanted = worktree_path.canonicalize().ok();
+    for name in names.iter().flatten() {
+        let Ok(worktree) = repo.find_worktree(name) else {
+            continue;
+        };
+        let same = worktree.path().components().eq(worktree_path.components())
+            || (wanted.is_some() && worktree.path().canonicalize().ok() == wanted);
+        if same {
+            return Ok(worktree);
+        }
+    }
+    anyhow::bail!(
+        "`{}` is not a linked worktree of `{}`",
+        worktree_path.display(),
+        repo_display_path(repo)
+    )
+}
+
+fn repo_display_path(repo: &git2::Repository) -> String {
+    repo.workdir()
+        .unwrap_or_else(|| repo.path())
+        .display()
+        .to_string()
 }
 
 pub(crate) fn build_worktree_destination(
@@ -121,10 +316,20 @@ pub(crate) fn build_worktree_destination(
     worktree_name: &str,
     worktree_root: &Path,
 ) -> anyhow::Result<PathBuf> {
-    let parent = worktree_root.join(slug_repo_path(main_repo_path));
+    let parent = slug_dir_for_repo(main_repo_path, worktree_root);
     Ok(parent.join(worktree_name))
 }
 
+/// Admin directory (`.git/worktrees/<id>`) that a linked worktree's `.git` file points at.
+pub(crate) fn worktree_admin_dir(worktree_path: &Path) -> anyhow::Result<PathBuf> {
+    let dot_git = worktree_path.join(".git");
+    if !dot_git.is_file() {
+        anyhow::bail!("`{}` is not a linked worktree", worktree_path.display());
+    }
+    let gitdir = read_gitdir_from_file(&dot_git)?;
+    Ok(resolve_gitdir_path(worktree_path, &gitdir))
+}
+
 fn read_gitdir_from_file(dot_git_path: &Path) -> anyhow::Result<PathBuf> {
     let contents = std::fs::read_to_string(dot_git_path)
         .with_context(|| format!("failed to read `{:?}`", dot_git_path))?;
@@ -159,6 +364,27 @@ fn parse_worktree_gitdir(gitdir: &Path) -> Option<(PathBuf, String)> {
     Some((main_repo, worktree_name))
 }
 
+/// Linked worktrees of every repo with a slug dir under any configured worktree root.
+///
+/// A repo found under several roots is listed once, since its worktrees are listed in full.
+pub(crate) fn discover_all_worktrees(
+    config_override: Option<&Path>,
+) -> anyhow::Result<Vec<(PathBuf, Vec<LinkedWorktreeDetails>)>> {
+    let mut results: Vec<(PathBuf, Vec<LinkedWorktreeDetails>)> = Vec::new();
+    for root in resolve_worktree_roots(config_override)? {
+        if !root.is_dir() {
+            log::warn!("worktree root `{}` does not exist", root.display());
+            continue;
+        }
+        for (main_repo, worktrees) in discover_all_worktrees_from_root(&root)? {
+            if !results.iter().any(|(seen, _)| *seen == main_repo) {
+                results.push((main_repo, worktrees));
+            }
+        }
+    }
+    Ok(results)
+}
+
 pub(crate) fn discover_all_worktrees_from_root(
     worktree_root: &Path,
 ) -> anyhow::Result<Vec<(PathBuf, Vec<LinkedWorktreeDetails>)>> {
@@ -185,12 +411,11 @@ pub(crate) fn discover_all_worktrees_from_root(
             continue;
         }
 
-        // Find the main repo by inspecting any child worktree
-        let main_repo = match find_main_repo_from_slug_dir(&slug_dir) {
+        let main_repo = match resolve_slug_dir_repo(&slug_dir) {
             Some(repo) => repo,
             None => {
                 log::warn!(
-                    "could not resolve main repo from slug dir `{}`",
+                    "could not resolve main repo from slug dir `{}`, see `shelf worktree doctor`",
                     slug_dir.display()
                 );
                 continue;
@@ -215,6 +440,99 @@ pub(crate) fn discover_all_worktrees_from_root(
     Ok(results)
 }
 
+/// Apparent size and allocated blocks of the files in a directory tree.
+///
+/// Symlinks are not followed, so `setup` symlinks into the main checkout are not counted,
+/// and a file with several hard links in the tree is counted once.
+pub(crate) fn disk_usage(path: &Path) -> DiskUsage {
+    let mut seen = HashSet::new();
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                log::debug!(
+                    "skipping entry while measuring `{}`: {}",
+                    path.display(),
+                    err
+                );
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.nlink() == 1 || seen.insert((metadata.dev(), metadata.ino())))
+        .fold(DiskUsage::default(), |mut usage, metadata| {
+            usage.apparent += metadata.len();
+            usage.on_disk += metadata.blocks() * DISK_BLOCK_SIZE;
+            usage
+        })
+}
+
+/// Slug directory that worktrees of `main_repo_path` are created in.
+pub(crate) fn slug_dir_for_repo(main_repo_path: &Path, worktree_root: &Path) -> PathBuf {
+    worktree_root.join(slug_repo_path(main_repo_path))
+}
+
+/// Main repo owning a slug directory, from its metadata file or else any child worktree.
+pub(crate) fn resolve_slug_dir_repo(slug_dir: &Path) -> Option<PathBuf> {
+    match read_slug_metadata(slug_dir) {
+        Ok(Some(metadata)) => return Some(metadata.main_repo),
+        Ok(None) => {}
+        Err(err) => log::warn!("{:#}", err),
+    }
+    find_main_repo_from_slug_dir(slug_dir)
+}
+
+pub(crate) fn read_slug_metadata(slug_dir: &Path) -> anyhow::Result<Option<SlugMetadata>> {
+    let path = slug_dir.join(SLUG_METADATA_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let file = std::fs::File::open(&path)
+        .with_context(|| format!("could not open slug metadata `{}`", path.display()))?;
+    let metadata = serde_yaml::from_reader(file)
+        .with_context(|| format!("could not parse slug metadata `{}`", path.display()))?;
+    Ok(Some(metadata))
+}
+
+/// Record the owning repo in a slug directory, leaving an existing metadata file alone.
+pub(crate) fn write_slug_metadata(slug_dir: &Path, main_repo_path: &Path) -> anyhow::Result<()> {
+    if slug_dir.join(SLUG_METADATA_FILE).exists() {
+        return Ok(());
+    }
+    replace_slug_metadata(slug_dir, main_repo_path)
+}
+
+/// Record the owning repo in a slug directory, for a repo that moved.
+pub(crate) fn replace_slug_metadata(slug_dir: &Path, main_repo_path: &Path) -> anyhow::Result<()> {
+    let path = slug_dir.join(SLUG_METADATA_FILE);
+    let metadata = SlugMetadata {
+        main_repo: main_repo_path.components().collect(),
+    };
+    let text = serde_yaml::to_string(&metadata).context("could not serialize slug metadata")?;
+    std::fs::write(&path, text)
+        .with_context(|| format!("could not write slug metadata `{}`", path.display()))
+}
+
+pub(crate) fn is_slug_metadata(path: &Path) -> bool {
+    path.file_name() == Some(std::ffi::OsStr::new(SLUG_METADATA_FILE))
+}
+
+/// Remove a slug directory that only has its metadata file left.
+pub(crate) fn remove_empty_slug_dir(slug_dir: &Path) -> anyhow::Result<bool> {
+    let has_worktrees = std::fs::read_dir(slug_dir)
+        .with_context(|| format!("failed to read `{}`", slug_dir.display()))?
+        .flatten()
+        .any(|entry| entry.file_name() != SLUG_METADATA_FILE);
+    if has_worktrees {
+        return Ok(false);
+    }
+    std::fs::remove_dir_all(slug_dir)
+        .with_context(|| format!("failed to remove `{}`", slug_dir.display()))?;
+    Ok(true)
+}
+
 fn find_main_repo_from_slug_dir(slug_dir: &Path) -> Option<PathBuf> {
     let entries = std::fs::read_dir(slug_dir).ok()?;
     for entry in entries {
@@ -253,75 +571,28 @@ fn slug_repo_path(main_repo_path: &Path) -> String {
         slug.push_str("repo");
     }
 
-    let mut hasher = DefaultHasher::new();
-    main_repo_path.hash(&mut hasher);
-    let hash = hasher.finish();
-
-    format!("{}-{:x}", slug, hash)
-}
-
-fn parse_worktree_list_porcelain(text: &str) -> Vec<LinkedWorktreeDetails> {
-    text.split("\n\n")
-        .filter_map(|block| {
-            let mut path: Option<PathBuf> = None;
-            let mut branch_ref = None;
-            let mut detached = false;
-            let mut locked = false;
-            let mut prunable = false;
-
-            for line in block.lines() {
-                if let Some(raw_path) = line.strip_prefix("worktree ").map(str::trim) {
-                    if !raw_path.is_empty() {
-                        path = Some(PathBuf::from(raw_path));
-                    }
-                    continue;
-                }
-
-                if let Some(raw_branch) = line.strip_prefix("branch ").map(str::trim) {
-                    if !raw_branch.is_empty() {
-                        branch_ref = Some(raw_branch.to_string());
-                    }
-                    continue;
-                }
-
-                if line.starts_with("detached") {
-                    detached = true;
-                    continue;
-                }
-
-                if line.starts_with("locked") {
-                    locked = true;
-                    continue;
-                }
-
-                if line.starts_with("prunable") {
-                    prunable = true;
-                }
-            }
+    format!("{}-{:08x}", slug, stable_path_hash(main_repo_path))
+}
 
-            Some((path?, branch_ref, detached, locked, prunable))
-        })
-        .map(|(p, branch_ref, detached, locked, prunable)| {
-            let name = p
-                .file_name()
-                .and_then(|n| n.to_str())
-                .map(|n| n.to_string())
-                .unwrap_or_else(|| p.display().to_string());
-            LinkedWorktreeDetails {
-                path: p,
-                name,
-                branch_ref,
-                detached,
-                locked,
-                prunable,
-            }
-        })
-        .collect()
+/// FNV-1a over the path components, folded to 32 bits.
+///
+/// Unlike `DefaultHasher` this never changes between Rust releases, so slug directories stay
+/// put across upgrades; hashing components ignores trailing slashes.
+fn stable_path_hash(path: &Path) -> u32 {
+    let mut hash = FNV_OFFSET_BASIS;
+    for component in path.components() {
+        for byte in component.as_os_str().as_bytes().iter().chain(b"/") {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    (hash ^ (hash >> 32)) as u32
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::git::test_util;
 
     fn unique_temp_path(name: &str) -> PathBuf {
         let nanos = std::time::SystemTime::now()
@@ -378,36 +649,116 @@ mod tests {
     }
 
     #[test]
-    fn parse_worktree_list_porcelain_extracts_paths_and_names() {
-        let text = "worktree /home/alice/src/github/org/repo\nHEAD 111\nbranch refs/heads/main\n\nworktree /home/alice/src/worktrees/github/org/repo/example\nHEAD 222\nbranch refs/heads/example\n\n";
+    fn slug_hash_is_stable_and_ignores_trailing_slash() {
+        let repo = Path::new("/home/alice/src/github/acme/foo");
 
-        let parsed = parse_worktree_list_porcelain(text);
+        assert_eq!(
+            slug_repo_path(repo),
+            format!(
+                "home-alice-src-github-acme-foo-{:08x}",
+                stable_path_hash(repo)
+            )
+        );
+        assert_eq!(stable_path_hash(repo), 0x8995_e42c);
+        assert_eq!(
+            slug_repo_path(Path::new("/home/alice/src/github/acme/foo/")),
+            slug_repo_path(repo)
+        );
+        assert_ne!(
+            slug_repo_path(Path::new("/home/alice/src/github/acme/bar")),
+            slug_repo_path(repo)
+        );
+    }
+
+    #[test]
+    fn slug_metadata_round_trips_and_resolves_repo() {
+        let slug_dir = unique_temp_path("slug-metadata");
+        std::fs::create_dir_all(&slug_dir).expect("create slug dir");
+        assert!(resolve_slug_dir_repo(&slug_dir).is_none());
+
+        write_slug_metadata(&slug_dir, Path::new("/src/acme/foo/")).expect("write metadata");
+        write_slug_metadata(&slug_dir, Path::new("/src/other")).expect("keep metadata");
 
-        assert_eq!(parsed.len(), 2);
         assert_eq!(
-            parsed[1],
-            LinkedWorktreeDetails {
-                path: PathBuf::from("/home/alice/src/worktrees/github/org/repo/example"),
-                name: "example".to_string(),
-                branch_ref: Some("refs/heads/example".to_string()),
-                detached: false,
-                locked: false,
-                prunable: false,
-            }
+            resolve_slug_dir_repo(&slug_dir),
+            Some(PathBuf::from("/src/acme/foo"))
         );
+        assert!(remove_empty_slug_dir(&slug_dir).expect("remove"));
+        assert!(!slug_dir.exists());
+    }
+
+    fn add_linked_worktree(repo: &git2::Repository, path: &Path, branch: &str) {
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let branch = repo.branch(branch, &head, false).unwrap();
+        let mut options = git2::WorktreeAddOptions::new();
+        options.reference(Some(branch.get()));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        repo.worktree(name, path, Some(&options))
+            .expect("add worktree");
     }
 
     #[test]
-    fn parse_worktree_list_porcelain_extracts_detached_locked_prunable() {
-        let text = "worktree /tmp/repo\nHEAD aaa\ndetached\nlocked reason\nprunable gone\n\n";
+    fn lists_linked_worktrees_with_lock_and_prunable_state() {
+        let root = unique_temp_path("list-worktrees");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+        add_linked_worktree(&repo, &root.join("wt/feature"), "feature");
+        add_linked_worktree(&repo, &root.join("wt/gone"), "gone");
+        lock_worktree(&main_repo, &root.join("wt/feature"), Some("usb disk")).expect("lock");
+        std::fs::remove_dir_all(root.join("wt/gone")).expect("remove worktree dir");
 
-        let parsed = parse_worktree_list_porcelain(text);
+        let listed = list_linked_worktree_details(&main_repo).expect("list");
+
+        assert_eq!(
+            listed,
+            vec![
+                LinkedWorktreeDetails {
+                    path: root.join("wt/feature"),
+                    name: "feature".to_string(),
+                    branch_ref: Some("refs/heads/feature".to_string()),
+                    detached: false,
+                    locked: true,
+                    prunable: false,
+                },
+                LinkedWorktreeDetails {
+                    path: root.join("wt/gone"),
+                    name: "gone".to_string(),
+                    branch_ref: Some("refs/heads/gone".to_string()),
+                    detached: false,
+                    locked: false,
+                    prunable: true,
+                },
+            ]
+        );
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
+    }
 
-        assert_eq!(parsed.len(), 1);
-        assert_eq!(parsed[0].branch_ref, None);
-        assert!(parsed[0].detached);
-        assert!(parsed[0].locked);
-        assert!(parsed[0].prunable);
+    #[test]
+    fn remove_refuses_locked_and_dirty_worktrees_unless_forced() {
+        let root = unique_temp_path("remove-worktrees");
+        let main_repo = root.join("repo");
+        let repo = test_util::init_repo(&main_repo);
+        let path = root.join("wt/feature");
+        add_linked_worktree(&repo, &path, "feature");
+        std::fs::write(path.join("README.md"), "changed\n").expect("modify file");
+
+        lock_worktree(&main_repo, &path, None).expect("lock");
+        let err = remove_worktree(&main_repo, &path, true).expect_err("locked");
+        assert!(err.to_string().contains("is locked"));
+        unlock_worktree(&main_repo, &path).expect("unlock");
+
+        let err = remove_worktree(&main_repo, &path, false).expect_err("dirty");
+        assert!(err.to_string().contains("uncommitted changes"));
+        remove_worktree(&main_repo, &path, true).expect("forced removal");
+
+        assert!(!path.exists());
+        assert!(list_linked_worktree_details(&main_repo)
+            .expect("list")
+            .is_empty());
+        let err = remove_worktree(&main_repo, &path, true).expect_err("already removed");
+        assert!(err.to_string().contains("is not a linked worktree"));
+        std::fs::remove_dir_all(&root).expect("cleanup repo");
     }
 
     #[test]
@@ -418,13 +769,55 @@ mod tests {
         std::fs::write(&config_path, "worktrees:\n  root: /tmp/custom-worktrees\n")
             .expect("write config");
 
-        let resolved = resolve_worktree_root(Some(&config_path)).expect("resolve worktree root");
+        let resolved = resolve_worktree_root(Some(&config_path), Path::new("/src/api"))
+            .expect("resolve worktree root");
         assert_eq!(resolved, PathBuf::from("/tmp/custom-worktrees"));
 
         std::fs::remove_file(&config_path).expect("cleanup config file");
         std::fs::remove_dir_all(&config_dir).expect("cleanup config dir");
     }
 
+    #[test]
+    fn resolve_worktree_root_prefers_git_config_then_project_group() {
+        let root = unique_temp_path("config-overrides");
+        let monorepo = root.join("src/work/mono");
+        let api = root.join("src/work/api");
+        test_util::init_repo(&monorepo);
+        let repo = test_util::init_repo(&api);
+        repo.config()
+            .unwrap()
+            .set_str("shelf.worktreeRoot", "/mnt/fast/api")
+            .unwrap();
+        let config_path = root.join("shelf.yml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "worktrees:\n  root: /tmp/worktrees\nprojects:\n  - root: {src}\n    title: src\n    extract: x\n  - root: {work}\n    title: work\n    extract: x\n    worktree_root: /mnt/big\n",
+                src = root.join("src").display(),
+                work = root.join("src/work").display(),
+            ),
+        )
+        .expect("write config");
+
+        assert_eq!(
+            resolve_worktree_root(Some(&config_path), &monorepo).unwrap(),
+            PathBuf::from("/mnt/big")
+        );
+        assert_eq!(
+            resolve_worktree_root(Some(&config_path), &api).unwrap(),
+            PathBuf::from("/mnt/fast/api")
+        );
+        assert_eq!(
+            resolve_worktree_root(Some(&config_path), &root.join("elsewhere")).unwrap(),
+            PathBuf::from("/tmp/worktrees")
+        );
+        assert_eq!(
+            resolve_worktree_roots(Some(&config_path)).unwrap(),
+            vec![PathBuf::from("/tmp/worktrees"), PathBuf::from("/mnt/big")]
+        );
+        std::fs::remove_dir_all(&root).expect("cleanup");
+    }
+
     #[test]
     fn resolve_worktree_root_errors_when_missing() {
         let config_dir = unique_temp_path("config-missing-root");
@@ -432,12 +825,29 @@ mod tests {
         let config_path = config_dir.join("shelf.yml");
         std::fs::write(&config_path, "projects: []\n").expect("write config");
 
-        let err =
-            resolve_worktree_root(Some(&config_path)).expect_err("expected missing root error");
+        let err = resolve_worktree_root(Some(&config_path), Path::new("/src/api"))
+            .expect_err("expected missing root error");
         let err_text = format!("{}", err);
         assert!(err_text.contains("missing `worktrees.root`"));
+        assert!(resolve_worktree_roots(Some(&config_path)).is_err());
 
         std::fs::remove_file(&config_path).expect("cleanup config file");
         std::fs::remove_dir_all(&config_dir).expect("cleanup config dir");
     }
+
+    #[test]
+    fn disk_usage_skips_symlinks_and_counts_hardlinks_once() {
+        let root = unique_temp_path("disk-usage");
+        std::fs::create_dir_all(root.join("nested")).expect("create dirs");
+        std::fs::write(root.join("a.txt"), "12345").expect("write file");
+        std::fs::write(root.join("nested/b.txt"), "123").expect("write file");
+        std::os::unix::fs::symlink(root.join("a.txt"), root.join("link")).expect("symlink");
+        std::fs::write(root.join("shared.txt"), "shared").expect("write file");
+        std::fs::hard_link(root.join("shared.txt"), root.join("nested/shared.txt"))
+            .expect("hardlink");
+
+        let usage = disk_usage(&root);
+        assert_eq!(usage.apparent, 14);
+        std::fs::remove_dir_all(&root).expect("cleanup dir");
+    }
 }
@@ -0,0 +1,382 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+use walkdir::WalkDir;
+
+use crate::config::{SetupEntry, SetupMode};
+
+const PORT_OFFSET_FILE: &str = "shelf-port-offset";
+
+/// Describes the worktree being prepared; also the source of template placeholders.
+pub(crate) struct SetupContext<'a> {
+    pub(crate) main_repo: &'a Path,
+    pub(crate) destination: &'a Path,
+    pub(crate) worktree_name: &'a str,
+    pub(crate) branch: Option<&'a str>,
+}
+
+/// Prepare each `worktrees.setup` entry in a new worktree.
+///
+/// Entries whose source is missing from the main repo, or whose target already exists, are
+/// skipped. The first failing entry stops setup; every target created until then, including
+/// a partial one, is pushed to `created` so the caller can undo it.
+pub(crate) fn apply_setup_entries(
+    ctx: &SetupContext<'_>,
+    setup: &[SetupEntry],
+    created: &mut Vec<PathBuf>,
+) -> anyhow::Result<()> {
+    let mut port_offset = None;
+    for entry in setup {
+        let target = ctx.destination.join(entry.path());
+        let existed = target.symlink_metadata().is_ok();
+        let result = apply_setup_entry(ctx, entry, &mut port_offset);
+        if !existed && target.symlink_metadata().is_ok() {
+            created.push(target);
+        }
+        result.with_context(|| format!("worktree setup `{}` failed", entry.path()))?;
+    }
+    Ok(())
+}
+
+/// Re-point `symlink` setup entries of a worktree that moved away from `old_destination`.
+///
+/// Links into the main repo stay valid on their own; links that now dangle or still point
+/// into the old location are recreated. Returns how many links were repaired.
+pub(crate) fn repair_setup_symlinks(
+    ctx: &SetupContext<'_>,
+    setup: &[SetupEntry],
+    old_destination: &Path,
+) -> usize {
+    let mut repaired = 0;
+    for entry in setup.iter().filter(|e| e.mode() == SetupMode::Symlink) {
+        let target = ctx.destination.join(entry.path());
+        let Ok(link) = std::fs::read_link(&target) else {
+            continue;
+        };
+        if target.exists() && !link.starts_with(old_destination) {
+            continue;
+        }
+        let source = ctx.main_repo.join(entry.source());
+        let result = remove_path(&target).and_then(|_| {
+            std::os::unix::fs::symlink(&source, &target)
+                .with_context(|| format!("failed to symlink `{}`", target.display()))
+        });
+        match result {
+            Ok(()) => repaired += 1,
+            Err(err) => log::warn!("worktree setup: `{}`: {:#}", entry.path(), err),
+        }
+    }
+    repaired
+}
+
+fn apply_setup_entry(
+    ctx: &SetupContext<'_>,
+    entry: &SetupEntry,
+    port_offset: &mut Option<u32>,
+) -> anyhow::Result<()> {
+    let source = ctx.main_repo.join(entry.source());
+    if !source.exists() {
+        return Ok(());
+    }
+    let target = ctx.destination.join(entry.path());
+    if target.exists() || target.symlink_metadata().is_ok() {
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create parent dirs for `{}`", target.display()))?;
+    }
+
+    match entry.mode() {
+        SetupMode::Symlink => std::os::unix::fs::symlink(&source, &target).with_context(|| {
+            format!(
+                "failed to symlink `{}` -> `{}`",
+                target.display(),
+                source.display()
+            )
+        }),
+        SetupMode::Copy => copy_recursive(&source, &target),
+        SetupMode::Reflink => {
+            if let Err(err) = reflink(&source, &target) {
+                log::debug!(
+                    "worktree setup: reflink of `{}` failed, copying instead: {:#}",
+                    source.display(),
+                    err
+                );
+                if target.symlink_metadata().is_ok() {
+                    remove_path(&target)?;
+                }
+                copy_recursive(&source, &target)?;
+            }
+            Ok(())
+        }
+        SetupMode::Template => {
+            let offset = match port_offset {
+                Some(offset) => *offset,
+                None => *port_offset.insert(allocate_port_offset(ctx.destination)?),
+            };
+            let text = std::fs::read_to_string(&source)
+                .with_context(|| format!("failed to read template `{}`", source.display()))?;
+            std::fs::write(&target, render_template(&text, ctx, offset))
+                .with_context(|| format!("failed to write `{}`", target.display()))
+        }
+    }
+}
+
+fn render_template(text: &str, ctx: &SetupContext<'_>, port_offset: u32) -> String {
+    text.replace("{{branch}}", ctx.branch.unwrap_or(""))
+        .replace("{{worktree}}", ctx.worktree_name)
+        .replace("{{repo}}", &ctx.main_repo.display().to_string())
+        .replace("{{port_offset}}", &port_offset.to_string())
+}
+
+/// Reserve the lowest offset (starting at 1) not held by another linked worktree of the repo.
+///
+/// The offset is stored in the worktree's admin dir, so git drops it when the worktree goes.
+fn allocate_port_offset(destination: &Path) -> anyhow::Result<u32> {
+    let admin_dir = super::worktree_admin_dir(destination)?;
+    let worktrees_dir = admin_dir
+        .parent()
+        .context("worktree admin dir has no parent")?;
+
+    let mut used = HashSet::new();
+    for entry in std::fs::read_dir(worktrees_dir)
+        .with_context(|| format!("failed to read `{}`", worktrees_dir.display()))?
+        .flatten()
+    {
+        if entry.path() == admin_dir {
+            continue;
+        }
+        if let Ok(text) = std::fs::read_to_string(entry.path().join(PORT_OFFSET_FILE)) {
+            if let Ok(offset) = text.trim().parse::<u32>() {
+                used.insert(offset);
+            }
+        }
+    }
+
+    let offset = (1..)
+        .find(|n| !used.contains(n))
+        .expect("ran out of port offsets");
+    std::fs::write(admin_dir.join(PORT_OFFSET_FILE), offset.to_string())
+        .context("failed to record port offset")?;
+    Ok(offset)
+}
+
+#[cfg(target_os = "macos")]
+const REFLINK_ARGS: &[&str] = &["-R", "-c"];
+#[cfg(not(target_os = "macos"))]
+const REFLINK_ARGS: &[&str] = &["-R", "--reflink=always"];
+
+fn reflink(source: &Path, target: &Path) -> anyhow::Result<()> {
+    let status = Command::new("cp")
+        .args(REFLINK_ARGS)
+        .arg(source)
+        .arg(target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("failed to execute `cp`")?;
+    if !status.success() {
+        anyhow::bail!("`cp {}` exited with {}", REFLINK_ARGS.join(" "), status);
+    }
+    Ok(())
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> anyhow::Result<()> {
+    for entry in WalkDir::new(source) {
+        let entry = entry.context("failed to walk setup source")?;
+        let relative = entry
+            .path()
+            .strip_prefix(source)
+            .expect("walkdir yields children of its root");
+        let dest = if relative.as_os_str().is_empty() {
+            target.to_path_buf()
+        } else {
+            target.join(relative)
+        };
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            std::fs::create_dir_all(&dest)
+                .with_context(|| format!("failed to create `{}`", dest.display()))?;
+        } else if file_type.is_symlink() {
+            let link = std::fs::read_link(entry.path())
+                .with_context(|| format!("failed to read link `{}`", entry.path().display()))?;
+            std::os::unix::fs::symlink(&link, &dest)
+                .with_context(|| format!("failed to create link `{}`", dest.display()))?;
+        } else {
+            std::fs::copy(entry.path(), &dest).with_context(|| {
+                format!(
+                    "failed to copy `{}` -> `{}`",
+                    entry.path().display(),
+                    dest.display()
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn remove_path(path: &Path) -> anyhow::Result<()> {
+    let result = if path.is_dir() && !path.is_symlink() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
+    };
+    result.with_context(|| format!("failed to remove `{}`", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::test_util::unique_temp_path;
+
+    fn entry(path: &str, mode: SetupMode, source: Option<&str>) -> SetupEntry {
+        SetupEntry::Detailed {
+            path: path.to_string(),
+            mode,
+            source: source.map(ToString::to_string),
+        }
+    }
+
+    /// Fake main repo and a linked worktree whose `.git` file points at an admin dir.
+    fn fixture(name: &str) -> (std::path::PathBuf, std::path::PathBuf, std::path::PathBuf) {
+        let root = unique_temp_path(name);
+        let main_repo = root.join("repo");
+        let destination = root.join("worktrees").join("feature");
+        let admin = main_repo.join(".git").join("worktrees").join("feature");
+        std::fs::create_dir_all(&admin).expect("create admin dir");
+        std::fs::create_dir_all(&destination).expect("create destination");
+        std::fs::write(
+            destination.join(".git"),
+            format!("gitdir: {}\n", admin.display()),
+        )
+        .expect("write .git file");
+        (root, main_repo, destination)
+    }
+
+    #[test]
+    fn setup_modes_link_copy_and_render() {
+        let (root, main_repo, destination) = fixture("setup-modes");
+        std::fs::write(main_repo.join(".envrc"), "use nix\n").unwrap();
+        std::fs::create_dir_all(main_repo.join("cache/nested")).unwrap();
+        std::fs::write(main_repo.join("cache/nested/data"), "cached\n").unwrap();
+        std::fs::write(
+            main_repo.join(".env.template"),
+            "BRANCH={{branch}}\nNAME={{worktree}}\nPORT=80{{port_offset}}\n",
+        )
+        .unwrap();
+        let setup = vec![
+            SetupEntry::Path(".envrc".to_string()),
+            entry("cache", SetupMode::Copy, None),
+            entry(".env", SetupMode::Template, Some(".env.template")),
+            entry("missing", SetupMode::Copy, None),
+        ];
+        let ctx = SetupContext {
+            main_repo: &main_repo,
+            destination: &destination,
+            worktree_name: "feature",
+            branch: Some("alice/feature"),
+        };
+
+        let mut created = Vec::new();
+        apply_setup_entries(&ctx, &setup, &mut created).expect("setup");
+
+        assert_eq!(
+            created,
+            vec![
+                destination.join(".envrc"),
+                destination.join("cache"),
+                destination.join(".env"),
+            ]
+        );
+        assert!(destination.join(".envrc").is_symlink());
+        let copied = destination.join("cache/nested/data");
+        assert!(!destination.join("cache").is_symlink());
+        assert_eq!(std::fs::read_to_string(copied).unwrap(), "cached\n");
+        assert_eq!(
+            std::fs::read_to_string(destination.join(".env")).unwrap(),
+            "BRANCH=alice/feature\nNAME=feature\nPORT=801\n"
+        );
+        assert!(!destination.join("missing").exists());
+        std::fs::remove_dir_all(&root).expect("cleanup");
+    }
+
+    #[test]
+    fn reflink_falls_back_to_copy() {
+        let (root, main_repo, destination) = fixture("setup-reflink");
+        std::fs::write(main_repo.join("data.bin"), "payload").unwrap();
+        let setup = vec![entry("data.bin", SetupMode::Reflink, None)];
+        let ctx = SetupContext {
+            main_repo: &main_repo,
+            destination: &destination,
+            worktree_name: "feature",
+            branch: None,
+        };
+
+        apply_setup_entries(&ctx, &setup, &mut Vec::new()).expect("setup");
+
+        let target = destination.join("data.bin");
+        assert!(!target.is_symlink());
+        assert_eq!(std::fs::read_to_string(target).unwrap(), "payload");
+        std::fs::remove_dir_all(&root).expect("cleanup");
+    }
+
+    #[test]
+    fn port_offset_skips_offsets_held_by_other_worktrees() {
+        let (root, main_repo, destination) = fixture("setup-port-offset");
+        let worktrees = main_repo.join(".git").join("worktrees");
+        for (name, offset) in [("a", "1"), ("b", "3")] {
+            std::fs::create_dir_all(worktrees.join(name)).unwrap();
+            std::fs::write(worktrees.join(name).join(PORT_OFFSET_FILE), offset).unwrap();
+        }
+
+        let offset = allocate_port_offset(&destination).expect("offset");
+
+        assert_eq!(offset, 2);
+        let recorded =
+            std::fs::read_to_string(worktrees.join("feature").join(PORT_OFFSET_FILE)).unwrap();
+        assert_eq!(recorded, "2");
+        std::fs::remove_dir_all(&root).expect("cleanup");
+    }
+
+    #[test]
+    fn repair_relinks_dangling_and_old_location_symlinks() {
+        let (root, main_repo, destination) = fixture("setup-repair");
+        std::fs::write(main_repo.join(".envrc"), "use nix\n").unwrap();
+        std::fs::write(main_repo.join(".tool-versions"), "rust 1.80\n").unwrap();
+        std::fs::write(main_repo.join("Makefile"), "all:\n").unwrap();
+        let old_destination = root.join("worktrees").join("old");
+        std::os::unix::fs::symlink(main_repo.join(".envrc"), destination.join(".envrc")).unwrap();
+        std::os::unix::fs::symlink(
+            old_destination.join(".tool-versions"),
+            destination.join(".tool-versions"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(root.join("gone"), destination.join("Makefile")).unwrap();
+        let setup = vec![
+            SetupEntry::Path(".envrc".to_string()),
+            SetupEntry::Path(".tool-versions".to_string()),
+            SetupEntry::Path("Makefile".to_string()),
+        ];
+        let ctx = SetupContext {
+            main_repo: &main_repo,
+            destination: &destination,
+            worktree_name: "feature",
+            branch: None,
+        };
+
+        assert_eq!(repair_setup_symlinks(&ctx, &setup, &old_destination), 2);
+        for path in [".envrc", ".tool-versions", "Makefile"] {
+            assert_eq!(
+                std::fs::read_link(destination.join(path)).unwrap(),
+                main_repo.join(path)
+            );
+        }
+        std::fs::remove_dir_all(&root).expect("cleanup");
+    }
+}